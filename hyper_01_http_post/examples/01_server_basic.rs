@@ -1,8 +1,14 @@
 use std::convert::Infallible;
 use std::net::SocketAddr;
-use hyper::{Body, Request, Response, Server};
-use hyper::service::{make_service_fn, service_fn};
-// use tokio::net::unix::SocketAddr;
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
 
 /*
  * run server:
@@ -12,33 +18,38 @@ use hyper::service::{make_service_fn, service_fn};
  * curl http://127.0.0.1:8000
  */
 
-
-async fn hello_world(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+async fn hello_world(_req: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
     println!("Handling a connection...");
-    Ok(Response::new("Hello, World".into()))
+    Ok(Response::new(Full::new(Bytes::from("Hello, World"))))
 }
 
-async fn app_main() {
-
+async fn app_main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let addr = SocketAddr::from(([127, 0, 0, 1], 8000));
-    // For every connection, make a Service to handle all incoming HTTP requests on said connection
-    let make_svc = make_service_fn(|_conn| async {
-
-        // 'service_fn' is a helper to convert a function that return a Response into a 'Service'
-        Ok::<_, Infallible>(service_fn(hello_world))
-    });
-
-    let server = Server::bind(&addr).serve(make_svc);
+    let listener = TcpListener::bind(addr).await?;
 
     println!("Listening on http://{}", addr);
 
-    if let Err(e) = server.await {
-        eprintln!("Server error: {}", e);
+    // For every connection, serve all incoming HTTP requests on said connection
+    loop {
+        let (stream, _) = listener.accept().await?;
+        // TokioIo: adapt tokio io traits to hyper io traits
+        let io = TokioIo::new(stream);
+
+        tokio::spawn(async move {
+            // 'service_fn' is a helper to convert a function that return a Response into a 'Service'
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(io, service_fn(hello_world))
+                .await
+            {
+                eprintln!("Server error: {}", e);
+            }
+        });
     }
 }
 
 fn main() {
     let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(app_main());
+    if let Err(e) = rt.block_on(app_main()) {
+        eprintln!("Server error: {}", e);
+    }
 }
-
@@ -1,10 +1,13 @@
-use std::convert::Infallible;
 use std::net::SocketAddr;
 
-use hyper::{Body, Request, Response, Server};
-use hyper::service::{make_service_fn, service_fn};
-use hyper::{Method, StatusCode};
-use futures::TryStreamExt as _;
+use bytes::Bytes;
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
 
 /*
  * cargo run --example 02_server_up
@@ -14,81 +17,94 @@ use futures::TryStreamExt as _;
  * curl http://127.0.0.1:8000/echo/reversed -X POST -d 'hello world'
  */
 
+type BoxedBody = BoxBody<Bytes, hyper::Error>;
 
-async fn echo(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+fn full<T: Into<Bytes>>(chunk: T) -> BoxedBody {
+    Full::new(chunk.into())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+fn empty() -> BoxedBody {
+    Empty::<Bytes>::new()
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+async fn echo(req: Request<Incoming>) -> Result<Response<BoxedBody>, hyper::Error> {
     println!("Handling a connection...");
     // Ok(Response::new("Hello, World".into()))
 
-    let mut response = Response::new(Body::empty());
+    let mut response = Response::new(empty());
 
     match (req.method(), req.uri().path()) {
-
         (&Method::GET, "/") => {
-            *response.body_mut() = Body::from("Try POST'ing data to /echo, e.g.: curl http://127.0.0.1:3000/echo -X POST -d 'hello world'");
-        },
+            *response.body_mut() = full(
+                "Try POST'ing data to /echo, e.g.: curl http://127.0.0.1:8000/echo -X POST -d 'hello world'",
+            );
+        }
 
         (&Method::POST, "/echo") => {
             // just echo back what was send
-            *response.body_mut() = req.into_body();
-        },
+            *response.body_mut() = req.into_body().boxed();
+        }
 
         (&Method::POST, "/echo/uppercase") => {
-            let mapping = req
-                .into_body()
-                .map_ok(|chunk| {
+            let mapping = req.into_body().map_frame(|frame| {
+                frame.map_data(|chunk| {
                     chunk
                         .iter()
                         .map(|byte| byte.to_ascii_uppercase())
-                        .collect::<Vec<u8>>()
-                });
-            *response.body_mut() = Body::wrap_stream(mapping);
-        },
+                        .collect::<Bytes>()
+                })
+            });
+            *response.body_mut() = mapping.boxed();
+        }
 
         (&Method::POST, "/echo/reversed") => {
-            let full_body: hyper::body::Bytes = hyper::body::to_bytes(req.into_body()).await?;
+            let full_body: Bytes = req.into_body().collect().await?.to_bytes();
             println!("full_body: {:?}", full_body);
             // iter() -> iterator over the slice
             // rev() -> (aka std::iter::Rev): reversed iterator
             // cloned() -> (aka std::iter::Cloned) iterator that clone the underlying iterator
-            let reversed: Vec<u8> = full_body
-                .iter()
-                .rev()
-                .cloned()
-                .collect::<Vec<u8>>();
-            *response.body_mut() = reversed.into();
-        },
+            let reversed: Vec<u8> = full_body.iter().rev().cloned().collect::<Vec<u8>>();
+            *response.body_mut() = full(reversed);
+        }
 
         _ => {
             *response.status_mut() = StatusCode::NOT_FOUND;
         }
-
     };
 
     Ok(response)
-
 }
 
-async fn app_main() {
-
+async fn app_main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let addr = SocketAddr::from(([127, 0, 0, 1], 8000));
-    // For every connection, make a Service to handle all incoming HTTP requests on said connection
-    let make_svc = make_service_fn(|_conn| async {
-        // 'service_fn' is a helper to convert a function that return a Response into a 'Service'
-        Ok::<_, Infallible>(service_fn(echo))
-    });
-
-    let server = Server::bind(&addr).serve(make_svc);
+    let listener = TcpListener::bind(addr).await?;
 
     println!("Listening on http://{}", addr);
 
-    if let Err(e) = server.await {
-        eprintln!("Server error: {}", e);
+    // For every connection, serve all incoming HTTP requests on said connection
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let io = TokioIo::new(stream);
+
+        tokio::spawn(async move {
+            // 'service_fn' is a helper to convert a function that return a Response into a 'Service'
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(io, service_fn(echo))
+                .await
+            {
+                eprintln!("Server error: {}", e);
+            }
+        });
     }
 }
 
-
 fn main() {
     let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(app_main());
+    if let Err(e) = rt.block_on(app_main()) {
+        eprintln!("Server error: {}", e);
+    }
 }
-
@@ -1,58 +1,219 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 use tokio::io::stdout;
 use tokio::io::AsyncWriteExt; // AsyncWriteExt trait
 
-use hyper::Client;
-use hyper::body::HttpBody as _; // HttpBody trait
-use hyper::{Body, Method, Request, Uri};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use hyper::{Method, Request, Response, Uri};
+use hyper_01_http_post::client::{ClientError, HttpClient};
+use rustls_pki_types::ServerName;
+use tls_config_lib::{parse_args, TlsClientBuilder};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/*
+ * cargo run --example 03_client
+ * HTTP2 without TLS (server started with --h2c):
+ * cargo run --example 03_client -- --http2
+ * HTTPS (http2 or http1.1 negotiated with ALPN, server started with --cert & --key):
+ * cargo run --example 03_client -- https://127.0.0.1:8443 --ca ../tokio_tcp_tls/certs/ca_signed/root_ca.pem --servername mydomain.com
+ * cargo run --example 03_client -- https://127.0.0.1:8443 --ca ../tokio_tcp_tls/certs/ca_signed/root_ca.pem --servername mydomain.com --http1
+ *
+ * WebSocket (ws or wss, server started with --cert & --key):
+ * cargo run --example 03_client -- ws://127.0.0.1:8000/ws
+ * cargo run --example 03_client -- wss://127.0.0.1:8443/ws --ca ../tokio_tcp_tls/certs/ca_signed/root_ca.pem --servername mydomain.com
+ *
+ * Timeouts & retries (GET requests are retried, POST only if the connection failed):
+ * cargo run --example 03_client -- --connect-timeout-ms 1000 --timeout-ms 5000 --retries 3
+ */
 
 type AError = Box<dyn std::error::Error + Send + Sync>;
 
+fn build_client(options: &HashMap<String, String>) -> Result<HttpClient, AError> {
+    let mut builder = HttpClient::builder();
+    if let Some(ms) = options.get("connect-timeout-ms") {
+        builder = builder.connect_timeout(Duration::from_millis(ms.parse()?));
+    }
+    if let Some(ms) = options.get("timeout-ms") {
+        builder = builder.request_timeout(Duration::from_millis(ms.parse()?));
+    }
+    if let Some(retries) = options.get("retries") {
+        builder = builder.max_retries(retries.parse()?);
+    }
+    // Root CA (pem) used to verify the server certificate (https)
+    if let Some(ca) = options.get("ca") {
+        builder = builder.tls(TlsClientBuilder::new().root_ca(ca).build()?);
+    }
+    // Server name (default: uri host), should match the cert subjectAltName
+    if let Some(servername) = options.get("servername") {
+        builder = builder.server_name(servername.as_str());
+    }
+    // http: use http2 with prior knowledge (h2c), https: only offer h2 with ALPN
+    if options.contains_key("http2") {
+        builder = builder.http2_only();
+    }
+    // https: only offer http/1.1 with ALPN
+    if options.contains_key("http1") {
+        builder = builder.http1_only();
+    }
+    Ok(builder.build()?)
+}
+
+async fn write_response(index: usize, resp: &Response<Bytes>) -> Result<(), AError> {
+    stdout()
+        .write_all(
+            format!(
+                "{}- Response status: {} ({:?})\n",
+                index,
+                resp.status(),
+                resp.version()
+            )
+            .as_ref(),
+        )
+        .await?;
+    stdout().write_all(b"Response content:\n").await?;
+    stdout().write_all(resp.body()).await?;
+    stdout().write_all("\n".as_ref()).await?;
+    Ok(())
+}
+
+/// Send a text, a binary & a ping message then close the connection
+async fn ws_session<S>(mut ws: WebSocketStream<S>) -> Result<(), AError>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let messages = [
+        Message::text("hello websocket"),
+        Message::binary(vec![1u8, 2, 3, 4]),
+        Message::Ping(Bytes::from("are you there?")),
+    ];
+    for message in messages {
+        println!("Sending: {:?}", message);
+        ws.send(message).await?;
+        match ws.next().await {
+            Some(reply) => println!("Received: {:?}", reply?),
+            None => return Err("Connection closed by the server".into()),
+        }
+    }
+
+    println!("Closing...");
+    ws.send(Message::Close(Some(CloseFrame {
+        code: CloseCode::Normal,
+        reason: "done".into(),
+    })))
+    .await?;
+    // the server replies with a close frame, then the stream ends
+    while let Some(message) = ws.next().await {
+        println!("Received: {:?}", message?);
+    }
+    Ok(())
+}
+
+async fn ws_main(url: &str, options: &HashMap<String, String>) -> Result<(), AError> {
+    let uri: Uri = url.parse()?;
+    let host = uri.host().ok_or("uri has no host")?;
+    let secure = uri.scheme_str() == Some("wss");
+    let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+    let stream = TcpStream::connect((host, port)).await?;
+
+    if !secure {
+        let (ws, response) = tokio_tungstenite::client_async(url, stream).await?;
+        println!("Connected: {}", response.status());
+        return ws_session(ws).await;
+    }
+
+    // Note: no ALPN, the server uses http/1.1 (the WebSocket upgrade is not supported with h2)
+    let mut builder = TlsClientBuilder::new();
+    if let Some(ca) = options.get("ca") {
+        builder = builder.root_ca(ca);
+    }
+    let connector = builder.build_connector()?;
+    let servername = options
+        .get("servername")
+        .map(|s| s.as_str())
+        .unwrap_or(host);
+    let tls_stream = connector
+        .connect(ServerName::try_from(servername.to_string())?, stream)
+        .await?;
+    let (ws, response) = tokio_tungstenite::client_async(url, tls_stream).await?;
+    println!("Connected (tls): {}", response.status());
+    ws_session(ws).await
+}
+
 async fn app_main() -> Result<(), AError> {
     // println!("app_main");
 
-    stdout().write_all(b"### Sending HTTP GET...\n").await?;
+    let (args, options) = parse_args();
+    if let Some(url) = args
+        .first()
+        .filter(|u| u.starts_with("ws://") || u.starts_with("wss://"))
+    {
+        return ws_main(url, &options).await;
+    }
+    let client = build_client(&options)?;
+    let base_url = args
+        .first()
+        .map(|u| u.trim_end_matches('/'))
+        .unwrap_or("http://127.0.0.1:8000");
 
-    let client = Client::new();
-    let uri = "http://127.0.0.1:8000".parse()?;
+    stdout().write_all(b"### Sending HTTP GET...\n").await?;
 
-    let mut resp = client.get(uri).await?; // HTTP GET
+    // Note: the Host header is set by the client (http1)
+    let resp = client.get(base_url).await?; // HTTP GET
+    write_response(1, &resp).await?;
     // response status, should be: 200 OK
-    assert_eq!(resp.status(), 200);
-    stdout().write_all(format!("1- Response status: {}\n", resp.status()).as_ref()).await?;
-
-    // get each chunk
-    stdout().write_all(b"Response content:\n").await?;
-    while let Some(chunk) = resp.body_mut().data().await {
-        stdout().write_all(&chunk?).await?;
+    if !resp.status().is_success() {
+        return Err(format!("Unexpected response status: {}", resp.status()).into());
     }
 
-    stdout().write_all("\n".as_ref()).await?;
-
     // POST
-    stdout().write_all(b"### Sending HTTP POST (to /echo)...\n").await?;
+    stdout()
+        .write_all(b"### Sending HTTP POST (to /echo)...\n")
+        .await?;
 
     let req = Request::builder()
         .method(Method::POST)
-        .uri("http://127.0.0.1:8000/echo")
+        .uri(format!("{}/echo", base_url))
         .header("content-type", "application/json")
-        .body(Body::from(r#"{"library":"hyper"}"#))?;
-
-    let mut resp = client.request(req).await?;
+        .body(Bytes::from(r#"{"library":"hyper"}"#))?;
+    let resp = client.send(req).await?;
+    write_response(2, &resp).await?;
 
-    // println!("2- Response: {}", resp.status());
-    stdout().write_all(format!("2- Response status: {}\n", resp.status()).as_ref()).await?;
+    // POST json, validated by the server & sent back as yaml
+    stdout()
+        .write_all(b"### Sending HTTP POST (to /echo/json)...\n")
+        .await?;
 
-    stdout().write_all(b"Response content:\n").await?;
-    while let Some(chunk) = resp.body_mut().data().await {
-        stdout().write_all(&chunk?).await?;
-    }
-    stdout().write_all("\n".as_ref()).await?;
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(format!("{}/echo/json", base_url))
+        .header("content-type", "application/json")
+        .header("accept", "application/yaml")
+        .body(Bytes::from(r#"{"library":"hyper"}"#))?;
+    let resp = client.send(req).await?;
+    write_response(3, &resp).await?;
 
     Ok(())
 }
 
 fn main() {
     let rt = tokio::runtime::Runtime::new().unwrap();
-    let _res = rt.block_on(app_main());
+    let res = rt.block_on(app_main());
     // println!("res: {:?}", _res);
+    if let Err(e) = res {
+        // typed errors: e.g. tell a timeout from a server not running
+        match e.downcast_ref::<ClientError>() {
+            Some(ClientError::ConnectionRefused(uri)) => {
+                eprintln!("Server is not running? connection refused: {}", uri)
+            }
+            Some(e) if e.is_timeout() => eprintln!("Timeout: {}", e),
+            _ => eprintln!("Client error: {}", e),
+        }
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,252 @@
+//! Request ids & access log (Common / Combined Log Format)
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use hyper::body::{Body, Frame, Incoming, SizeHint};
+use hyper::header::{HeaderValue, REFERER, USER_AGENT};
+use hyper::{Request, Response};
+use rand::Rng;
+
+use crate::body::{BoxError, BoxedBody};
+use crate::router::{HandlerFuture, Middleware, Next, Params};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Address of the client (inserted in the request extensions when the connection is accepted)
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteAddr(pub SocketAddr);
+
+/// Only propagate "safe" request ids (no spaces or quotes in the logs)
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+fn generate_request_id() -> String {
+    let bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Give each request an id: the X-Request-Id sent by the client (if valid) or a generated one
+///
+/// The id is set in the request headers (for the next middlewares & the handler) and in the
+/// response headers
+pub struct RequestId;
+
+impl Middleware for RequestId {
+    fn call(&self, mut req: Request<Incoming>, params: Params, next: Next) -> HandlerFuture {
+        let id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|id| is_valid_request_id(id))
+            .map(|id| id.to_string())
+            .unwrap_or_else(generate_request_id);
+        // valid header value: checked or generated (hex)
+        let value = HeaderValue::from_str(&id).expect("valid request id");
+        req.headers_mut().insert(REQUEST_ID_HEADER, value.clone());
+        Box::pin(async move {
+            let mut response = next.run(req, params).await?;
+            response.headers_mut().insert(REQUEST_ID_HEADER, value);
+            Ok(response)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// host ident user [time] "request line" status size
+    Common,
+    /// Common + "referer" "user agent"
+    Combined,
+}
+
+/// Write an access log line per request, once the response body has been sent (or the client
+/// has gone): Common or Combined Log Format followed by the latency & the request id
+///
+/// 127.0.0.1 - - [19/Oct/2026:08:30:00 +0200] "POST /echo HTTP/1.1" 200 11 "-" "curl/8.5.0" 0.412ms 3f2a...
+pub struct AccessLog {
+    pub format: LogFormat,
+}
+
+/// Request fields, logged once the response is complete
+struct LogEntry {
+    start: Instant,
+    /// host ident user [time] "request line"
+    prefix: String,
+    /// Combined format: "referer" "user agent"
+    suffix: String,
+    request_id: String,
+}
+
+impl LogEntry {
+    fn write(&self, status: u16, size: u64) {
+        let size = match size {
+            0 => "-".to_string(),
+            size => size.to_string(),
+        };
+        println!(
+            "{} {} {}{} {} {}",
+            self.prefix,
+            status,
+            size,
+            self.suffix,
+            format_latency(self.start.elapsed()),
+            self.request_id
+        );
+    }
+}
+
+fn format_latency(latency: Duration) -> String {
+    format!("{:.3}ms", latency.as_secs_f64() * 1000.0)
+}
+
+fn header_or_dash(req: &Request<Incoming>, name: hyper::header::HeaderName) -> String {
+    req.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        // quotes would break the log format
+        .map(|v| v.replace('"', "\\\""))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+impl Middleware for AccessLog {
+    fn call(&self, req: Request<Incoming>, params: Params, next: Next) -> HandlerFuture {
+        let host = req
+            .extensions()
+            .get::<RemoteAddr>()
+            .map(|addr| addr.0.ip().to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let time = chrono::Local::now().format("%d/%b/%Y:%H:%M:%S %z");
+        let path = req
+            .uri()
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/");
+        let entry = LogEntry {
+            start: Instant::now(),
+            prefix: format!(
+                "{} - - [{}] \"{} {} {:?}\"",
+                host,
+                time,
+                req.method(),
+                path,
+                req.version()
+            ),
+            suffix: match self.format {
+                LogFormat::Common => String::new(),
+                LogFormat::Combined => format!(
+                    " \"{}\" \"{}\"",
+                    header_or_dash(&req, REFERER),
+                    header_or_dash(&req, USER_AGENT)
+                ),
+            },
+            request_id: req
+                .headers()
+                .get(REQUEST_ID_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("-")
+                .to_string(),
+        };
+
+        Box::pin(async move {
+            match next.run(req, params).await {
+                Ok(response) => {
+                    let (parts, body) = response.into_parts();
+                    let body = LoggedBody {
+                        inner: body,
+                        status: parts.status.as_u16(),
+                        sent: 0,
+                        entry: Some(entry),
+                    };
+                    Ok(Response::from_parts(parts, BoxedBody::new(body)))
+                }
+                Err(e) => {
+                    // turned into a 500 by the router
+                    entry.write(500, 0);
+                    Err(e)
+                }
+            }
+        })
+    }
+}
+
+/// A response body counting the bytes sent, the log line is written at the end of the body
+/// (or when it is dropped: client gone, connection closed)
+struct LoggedBody {
+    inner: BoxedBody,
+    status: u16,
+    sent: u64,
+    entry: Option<LogEntry>,
+}
+
+impl LoggedBody {
+    fn log(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            entry.write(self.status, self.sent);
+        }
+    }
+}
+
+impl Body for LoggedBody {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = Pin::new(&mut self.inner).poll_frame(cx);
+        match &frame {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.sent += data.len() as u64;
+                }
+            }
+            Poll::Ready(None) => self.log(),
+            _ => {}
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        self.log();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id() {
+        assert!(is_valid_request_id("3f2a-42_b.c:1"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("a b"));
+        assert!(!is_valid_request_id("a\"b"));
+        assert!(!is_valid_request_id(&"a".repeat(129)));
+
+        let id = generate_request_id();
+        assert_eq!(id.len(), 32);
+        assert!(is_valid_request_id(&id));
+        assert_ne!(id, generate_request_id());
+    }
+}
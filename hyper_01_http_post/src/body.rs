@@ -0,0 +1,235 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::{Bytes, BytesMut};
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full, Limited};
+use hyper::body::Body;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+// Note: hyper 1.x has no Body type anymore, a response body is anything that impl the Body trait
+//       here all responses use a boxed body so every route can return a different body type
+pub type BoxedBody = BoxBody<Bytes, BoxError>;
+
+const DEFAULT_MAX_BODY_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_SPILL_THRESHOLD: u64 = 1024 * 1024;
+
+pub fn full<T: Into<Bytes>>(chunk: T) -> BoxedBody {
+    Full::new(chunk.into())
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+pub fn empty() -> BoxedBody {
+    Empty::<Bytes>::new()
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+/// Request body limits
+#[derive(Debug, Clone)]
+pub struct BodyLimits {
+    /// Max request body size (413 Payload Too Large beyond)
+    pub max_size: u64,
+    /// A buffered body is written to a temp file beyond this size
+    pub spill_threshold: u64,
+    /// Where temp files are written
+    pub spill_dir: PathBuf,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        Self {
+            max_size: DEFAULT_MAX_BODY_SIZE,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
+            spill_dir: std::env::temp_dir(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BufferError {
+    #[error("Body is larger than the limit ({0} bytes)")]
+    TooLarge(u64),
+    #[error("Unable to read body: {0}")]
+    Body(BoxError),
+    #[error("Unable to write temp file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Stream a body, at most `max_size` bytes (the body returns an error beyond)
+///
+/// Note: data is only read from the request when the returned body is polled, so a slow
+///       reader (e.g. the client reading the response) slows down the writer (backpressure)
+pub fn limited<B>(body: B, max_size: u64) -> BoxedBody
+where
+    B: Body<Data = Bytes> + Send + Sync + 'static,
+    B::Error: Into<BoxError>,
+{
+    Limited::new(body, max_size as usize).boxed()
+}
+
+/// Read a whole body in memory (at most `max_size` bytes)
+pub async fn collect_limited<B>(body: B, max_size: u64) -> Result<Bytes, BufferError>
+where
+    B: Body<Data = Bytes>,
+    B::Error: Into<BoxError>,
+{
+    match Limited::new(body, max_size as usize).collect().await {
+        Ok(collected) => Ok(collected.to_bytes()),
+        Err(e) if e.is::<http_body_util::LengthLimitError>() => {
+            Err(BufferError::TooLarge(max_size))
+        }
+        Err(e) => Err(BufferError::Body(e)),
+    }
+}
+
+/// A temp file, removed on drop
+#[derive(Debug)]
+pub struct SpillFile {
+    path: PathBuf,
+    len: u64,
+}
+
+impl SpillFile {
+    fn new_path(dir: &Path) -> PathBuf {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        dir.join(format!("hyper_echo_{}_{}.tmp", std::process::id(), n))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Read `len` bytes at `offset`
+    pub async fn read_at(&self, offset: u64, len: usize) -> std::io::Result<Bytes> {
+        let mut file = tokio::fs::File::open(&self.path).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        let mut buffer = BytesMut::zeroed(len);
+        file.read_exact(&mut buffer).await?;
+        Ok(buffer.freeze())
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            eprintln!("Unable to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// A fully read request body
+#[derive(Debug)]
+pub enum Buffered {
+    Memory(Bytes),
+    File(SpillFile),
+}
+
+/// Read a whole body: in memory up to `spill_threshold` bytes then in a temp file,
+/// error if the body is larger than `max_size`
+pub async fn buffer_body<B>(mut body: B, limits: &BodyLimits) -> Result<Buffered, BufferError>
+where
+    B: Body<Data = Bytes> + Unpin,
+    B::Error: Into<BoxError>,
+{
+    let mut memory = BytesMut::new();
+    let mut spill: Option<(tokio::fs::File, SpillFile)> = None;
+    let mut size: u64 = 0;
+
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|e| BufferError::Body(e.into()))?;
+        let Ok(chunk) = frame.into_data() else {
+            // trailers
+            continue;
+        };
+        size += chunk.len() as u64;
+        if size > limits.max_size {
+            return Err(BufferError::TooLarge(limits.max_size));
+        }
+
+        if spill.is_none() && size > limits.spill_threshold {
+            let path = SpillFile::new_path(&limits.spill_dir);
+            let mut file = tokio::fs::File::create(&path).await?;
+            // the SpillFile guard removes the file on error / drop
+            let spill_file = SpillFile { path, len: 0 };
+            file.write_all(&memory).await?;
+            memory.clear();
+            spill = Some((file, spill_file));
+        }
+        match spill.as_mut() {
+            Some((file, _)) => file.write_all(&chunk).await?,
+            None => memory.extend_from_slice(&chunk),
+        }
+    }
+
+    match spill {
+        Some((mut file, mut spill_file)) => {
+            file.flush().await?;
+            spill_file.len = size;
+            Ok(Buffered::File(spill_file))
+        }
+        None => Ok(Buffered::Memory(memory.freeze())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_size: u64, spill_threshold: u64) -> BodyLimits {
+        BodyLimits {
+            max_size,
+            spill_threshold,
+            spill_dir: std::env::temp_dir(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_buffer_body() {
+        let body = Full::new(Bytes::from("hello world"));
+        match buffer_body(body, &limits(100, 50)).await.unwrap() {
+            Buffered::Memory(bytes) => assert_eq!(bytes, "hello world"),
+            b => panic!("Expected a memory buffer, got: {:?}", b),
+        }
+
+        let body = Full::new(Bytes::from("hello world"));
+        let path = match buffer_body(body, &limits(100, 5)).await.unwrap() {
+            Buffered::File(file) => {
+                assert_eq!(file.len(), 11);
+                assert_eq!(file.read_at(6, 5).await.unwrap(), "world");
+                file.path().to_path_buf()
+            }
+            b => panic!("Expected a file buffer, got: {:?}", b),
+        };
+        // removed on drop
+        assert!(!path.exists());
+
+        let body = Full::new(Bytes::from("hello world"));
+        assert!(matches!(
+            buffer_body(body, &limits(10, 5)).await,
+            Err(BufferError::TooLarge(10))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_collect_limited() {
+        let body = Full::new(Bytes::from("hello"));
+        assert_eq!(collect_limited(body, 5).await.unwrap(), "hello");
+        let body = Full::new(Bytes::from("hello"));
+        assert!(matches!(
+            collect_limited(body, 4).await,
+            Err(BufferError::TooLarge(4))
+        ));
+    }
+}
@@ -0,0 +1,483 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use hyper_rustls::{FixedServerNameResolver, HttpsConnector};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+use rand::Rng;
+use rustls::{ClientConfig, RootCertStore};
+use rustls_pki_types::ServerName;
+
+use crate::body::BoxError;
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+pub(crate) const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_TOTAL_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const DEFAULT_BACKOFF_BASE: Duration = Duration::from_millis(100);
+const DEFAULT_BACKOFF_MAX: Duration = Duration::from_secs(2);
+const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 16;
+const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("Invalid client config: {0}")]
+    Config(BoxError),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] hyper::http::Error),
+    #[error("Connection refused by {0}")]
+    ConnectionRefused(Uri),
+    #[error("Unable to connect to {0} in {1:?}")]
+    ConnectTimeout(Uri, Duration),
+    #[error("Unable to connect to {0}: {cause}", cause = error_chain(.1.as_ref()))]
+    Connect(Uri, BoxError),
+    #[error("No response in {0:?}")]
+    RequestTimeout(Duration),
+    #[error("Request not completed in {0:?} (retries included)")]
+    TotalTimeout(Duration),
+    #[error("Request failed: {cause}", cause = error_chain(.0.as_ref()))]
+    Request(BoxError),
+    #[error("Unable to read response body: {0}")]
+    Body(hyper::Error),
+}
+
+/// hyper errors display a generic message (e.g. "client error (Connect)"), the cause is in the
+/// error sources
+fn error_chain(e: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
+
+impl ClientError {
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            ClientError::ConnectTimeout(..)
+                | ClientError::RequestTimeout(_)
+                | ClientError::TotalTimeout(_)
+        )
+    }
+
+    /// The request has not been sent (so it can be retried, whatever its method)
+    fn is_connect(&self) -> bool {
+        matches!(
+            self,
+            ClientError::ConnectionRefused(_)
+                | ClientError::ConnectTimeout(..)
+                | ClientError::Connect(..)
+        )
+    }
+
+    /// Map a hyper_util client error, using the io error (if any) to find the cause
+    fn from_client_error(
+        e: hyper_util::client::legacy::Error,
+        uri: &Uri,
+        connect_timeout: Duration,
+    ) -> Self {
+        if !e.is_connect() {
+            return ClientError::Request(Box::new(e));
+        }
+        let mut source = std::error::Error::source(&e);
+        while let Some(err) = source {
+            if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+                match io_err.kind() {
+                    std::io::ErrorKind::ConnectionRefused => {
+                        return ClientError::ConnectionRefused(uri.clone())
+                    }
+                    std::io::ErrorKind::TimedOut => {
+                        return ClientError::ConnectTimeout(uri.clone(), connect_timeout)
+                    }
+                    _ => {}
+                }
+            }
+            source = err.source();
+        }
+        ClientError::Connect(uri.clone(), Box::new(e))
+    }
+}
+
+/// Requests that can be sent multiple times with the same effect (RFC 9110)
+pub fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// Build an HttpClient
+///
+/// Timeouts:
+/// * connect: tcp connect (per attempt)
+/// * request: until the response headers are received (per attempt)
+/// * total: the whole request, retries & response body included
+#[derive(Debug)]
+pub struct HttpClientBuilder {
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    total_timeout: Duration,
+    max_retries: u32,
+    backoff_base: Duration,
+    backoff_max: Duration,
+    pool_max_idle_per_host: usize,
+    pool_idle_timeout: Duration,
+    tls: Option<ClientConfig>,
+    server_name: Option<String>,
+    http1_only: bool,
+    http2_only: bool,
+}
+
+impl Default for HttpClientBuilder {
+    fn default() -> Self {
+        Self {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            total_timeout: DEFAULT_TOTAL_TIMEOUT,
+            max_retries: DEFAULT_MAX_RETRIES,
+            backoff_base: DEFAULT_BACKOFF_BASE,
+            backoff_max: DEFAULT_BACKOFF_MAX,
+            pool_max_idle_per_host: DEFAULT_POOL_MAX_IDLE_PER_HOST,
+            pool_idle_timeout: DEFAULT_POOL_IDLE_TIMEOUT,
+            tls: None,
+            server_name: None,
+            http1_only: false,
+            http2_only: false,
+        }
+    }
+}
+
+impl HttpClientBuilder {
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    pub fn total_timeout(mut self, timeout: Duration) -> Self {
+        self.total_timeout = timeout;
+        self
+    }
+
+    /// Max number of retries (0: no retry)
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Delay before the first retry, doubled for each retry (up to max)
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.backoff_base = base;
+        self.backoff_max = max;
+        self
+    }
+
+    /// Max number of idle connections kept (per host)
+    pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> Self {
+        self.pool_max_idle_per_host = max_idle;
+        self
+    }
+
+    /// Idle connections are closed after this delay
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Tls config used for https uris (e.g. built with tls_config_lib::TlsClientBuilder)
+    ///
+    /// Note: without a tls config, no server certificate can be verified (https fails)
+    pub fn tls(mut self, config: ClientConfig) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Server name sent with SNI & verified (default: uri host)
+    pub fn server_name<S: Into<String>>(mut self, server_name: S) -> Self {
+        self.server_name = Some(server_name.into());
+        self
+    }
+
+    /// Only http/1.1 (https: only offered with ALPN)
+    pub fn http1_only(mut self) -> Self {
+        self.http1_only = true;
+        self.http2_only = false;
+        self
+    }
+
+    /// Only http2 (http: with prior knowledge, https: only offered with ALPN)
+    pub fn http2_only(mut self) -> Self {
+        self.http2_only = true;
+        self.http1_only = false;
+        self
+    }
+
+    pub fn build(self) -> Result<HttpClient, ClientError> {
+        let mut http = HttpConnector::new();
+        // https uris are handled by the HttpsConnector
+        http.enforce_http(false);
+        http.set_connect_timeout(Some(self.connect_timeout));
+
+        let tls = match self.tls {
+            Some(config) => config,
+            None => ClientConfig::builder_with_provider(
+                rustls::crypto::aws_lc_rs::default_provider().into(),
+            )
+            .with_safe_default_protocol_versions()
+            .map_err(|e| ClientError::Config(Box::new(e)))?
+            .with_root_certificates(RootCertStore::empty())
+            .with_no_client_auth(),
+        };
+        let mut https = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_tls_config(tls)
+            .https_or_http();
+        if let Some(server_name) = self.server_name {
+            let server_name =
+                ServerName::try_from(server_name).map_err(|e| ClientError::Config(Box::new(e)))?;
+            https = https.with_server_name_resolver(FixedServerNameResolver::new(server_name));
+        }
+        let connector: HttpsConnector<HttpConnector> = match (self.http1_only, self.http2_only) {
+            (true, _) => https.enable_http1().wrap_connector(http),
+            (_, true) => https.enable_http2().wrap_connector(http),
+            _ => https.enable_all_versions().wrap_connector(http),
+        };
+
+        let client = Client::builder(TokioExecutor::new())
+            .timer(TokioTimer::new())
+            .pool_timer(TokioTimer::new())
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .http2_only(self.http2_only)
+            .build(connector);
+
+        Ok(HttpClient {
+            client,
+            connect_timeout: self.connect_timeout,
+            request_timeout: self.request_timeout,
+            total_timeout: self.total_timeout,
+            max_retries: self.max_retries,
+            backoff_base: self.backoff_base,
+            backoff_max: self.backoff_max,
+        })
+    }
+}
+
+/// An http client (pooled connections) with timeouts & retries
+///
+/// Requests are retried with an exponential backoff (with jitter):
+/// * any request if the connection failed (the request has not been sent)
+/// * idempotent requests on timeout, request error or 502 / 503 / 504 responses
+///
+/// Request & response bodies are fully buffered (so a request can be sent again)
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    total_timeout: Duration,
+    max_retries: u32,
+    backoff_base: Duration,
+    backoff_max: Duration,
+}
+
+impl HttpClient {
+    pub fn builder() -> HttpClientBuilder {
+        HttpClientBuilder::default()
+    }
+
+    pub async fn get(&self, uri: &str) -> Result<Response<Bytes>, ClientError> {
+        let req = Request::get(uri).body(Bytes::new())?;
+        self.send(req).await
+    }
+
+    pub async fn post<B: Into<Bytes>>(
+        &self,
+        uri: &str,
+        body: B,
+    ) -> Result<Response<Bytes>, ClientError> {
+        let req = Request::post(uri).body(body.into())?;
+        self.send(req).await
+    }
+
+    /// Send a request (with retries), the response body is fully read
+    pub async fn send(&self, req: Request<Bytes>) -> Result<Response<Bytes>, ClientError> {
+        match tokio::time::timeout(self.total_timeout, self.send_with_retries(req)).await {
+            Ok(result) => result,
+            Err(_) => Err(ClientError::TotalTimeout(self.total_timeout)),
+        }
+    }
+
+    async fn send_with_retries(&self, req: Request<Bytes>) -> Result<Response<Bytes>, ClientError> {
+        let idempotent = is_idempotent(req.method());
+        let mut attempt = 0;
+        loop {
+            let result = self.send_once(&req).await;
+            let retry = match &result {
+                Ok(resp) => idempotent && is_retryable_status(resp.status()),
+                Err(e) => e.is_connect() || (idempotent && !matches!(e, ClientError::Body(_))),
+            };
+            if !retry || attempt >= self.max_retries {
+                return result;
+            }
+
+            let delay = backoff(attempt, self.backoff_base, self.backoff_max);
+            match &result {
+                Ok(resp) => println!(
+                    "[client] {} {} -> {}, retry in {:?}",
+                    req.method(),
+                    req.uri(),
+                    resp.status(),
+                    delay
+                ),
+                Err(e) => println!(
+                    "[client] {} {} failed: {}, retry in {:?}",
+                    req.method(),
+                    req.uri(),
+                    e,
+                    delay
+                ),
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send_once(&self, req: &Request<Bytes>) -> Result<Response<Bytes>, ClientError> {
+        let mut builder = Request::builder()
+            .method(req.method())
+            .uri(req.uri())
+            .version(req.version());
+        if let Some(headers) = builder.headers_mut() {
+            headers.extend(req.headers().clone());
+        }
+        let attempt_req = builder.body(Full::new(req.body().clone()))?;
+
+        let resp = match tokio::time::timeout(
+            self.request_timeout,
+            self.client.request(attempt_req),
+        )
+        .await
+        {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(e)) => {
+                return Err(ClientError::from_client_error(
+                    e,
+                    req.uri(),
+                    self.connect_timeout,
+                ))
+            }
+            Err(_) => return Err(ClientError::RequestTimeout(self.request_timeout)),
+        };
+        let (parts, body) = resp.into_parts();
+        let body = body.collect().await.map_err(ClientError::Body)?.to_bytes();
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Delay before retry #attempt: base * 2^attempt (up to max), with a random jitter
+/// (between half and the full delay) so clients do not retry all at the same time
+fn backoff(attempt: u32, base: Duration, max: Duration) -> Duration {
+    let delay = base.saturating_mul(2u32.saturating_pow(attempt)).min(max);
+    let half = delay / 2;
+    half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let base = Duration::from_millis(100);
+        let max = Duration::from_secs(1);
+        for (attempt, expected) in [
+            (0, 100),
+            (1, 200),
+            (2, 400),
+            (3, 800),
+            (4, 1000),
+            (40, 1000),
+        ] {
+            let delay = backoff(attempt, base, max);
+            let expected = Duration::from_millis(expected);
+            assert!(
+                delay >= expected / 2 && delay <= expected,
+                "attempt {}: {:?}",
+                attempt,
+                delay
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_idempotent() {
+        assert!(is_idempotent(&Method::GET));
+        assert!(is_idempotent(&Method::PUT));
+        assert!(!is_idempotent(&Method::POST));
+        assert!(!is_idempotent(&Method::PATCH));
+    }
+
+    #[tokio::test]
+    async fn test_connection_refused() {
+        // bind then drop: nobody listens on this port anymore
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let client = HttpClient::builder()
+            .max_retries(1)
+            .backoff(Duration::from_millis(1), Duration::from_millis(1))
+            .build()
+            .unwrap();
+        let res = client.get(&format!("http://{}/", addr)).await;
+        assert!(
+            matches!(res, Err(ClientError::ConnectionRefused(_))),
+            "{:?}",
+            res
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        // accept connections but never answer
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut streams = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+
+        let client = HttpClient::builder()
+            .request_timeout(Duration::from_millis(50))
+            .max_retries(0)
+            .build()
+            .unwrap();
+        let res = client.get(&format!("http://{}/", addr)).await;
+        assert!(
+            matches!(res, Err(ClientError::RequestTimeout(_))),
+            "{:?}",
+            res
+        );
+        assert!(res.unwrap_err().is_timeout());
+    }
+}
@@ -0,0 +1,325 @@
+//! Response compression (gzip, deflate, br) negotiated with Accept-Encoding & decompression
+//! of request bodies (Content-Encoding)
+//!
+//! Bodies are (de)compressed as streams: chunks are read, (de)compressed and sent as soon as
+//! the encoder produces some output, a body is never fully buffered.
+
+use async_compression::tokio::bufread::{
+    BrotliDecoder, BrotliEncoder, GzipDecoder, GzipEncoder, ZlibDecoder, ZlibEncoder,
+};
+use bytes::Bytes;
+use futures::TryStreamExt;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::{Body, Frame};
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+    CONTENT_TYPE, ETAG, VARY,
+};
+use hyper::{Method, Request, Response, StatusCode};
+use tokio::io::{AsyncBufRead, AsyncRead};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::body::{empty, BoxError, BoxedBody};
+use crate::router::{HandlerFuture, Middleware, Next, Params};
+
+const DEFAULT_MIN_SIZE: u64 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    /// zlib format (as specified for http "deflate", RFC 9110)
+    Deflate,
+}
+
+impl Encoding {
+    /// By order of preference (used if q-values are equal)
+    pub const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "br" => Some(Encoding::Brotli),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+}
+
+/// Parse an Accept-Encoding header: (coding, q-value) pairs
+fn parse_accept_encoding(value: &str) -> Vec<(String, f32)> {
+    value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let coding = parts.next()?.trim().to_ascii_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            Some((coding, q))
+        })
+        .collect()
+}
+
+/// Choose the response encoding: the supported encoding with the highest q-value
+/// (None: identity)
+pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+    let accepted = parse_accept_encoding(accept_encoding);
+    let wildcard = accepted
+        .iter()
+        .find(|(coding, _)| coding == "*")
+        .map(|(_, q)| *q);
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in Encoding::ALL {
+        let q = accepted
+            .iter()
+            .find(|(coding, _)| Encoding::parse(coding) == Some(encoding))
+            .map(|(_, q)| *q)
+            .or(wildcard)
+            .unwrap_or(0.0);
+        // strictly greater: on equal q-values, keep the preferred encoding
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Convert a body to an AsyncBufRead (of its data frames)
+fn body_reader<B>(body: B) -> impl AsyncBufRead + Send + Sync + Unpin + 'static
+where
+    B: Body<Data = Bytes> + Send + Sync + Unpin + 'static,
+    B::Error: Into<BoxError>,
+{
+    let stream = body
+        .into_data_stream()
+        .map_err(|e| std::io::Error::other(e.into()));
+    StreamReader::new(stream)
+}
+
+/// Stream an AsyncRead as a body
+fn reader_body<R>(reader: R) -> BoxedBody
+where
+    R: AsyncRead + Send + Sync + 'static,
+{
+    let stream = ReaderStream::new(reader)
+        .map_ok(Frame::data)
+        .map_err(|e| -> BoxError { Box::new(e) });
+    BoxedBody::new(StreamBody::new(stream))
+}
+
+pub fn compress<B>(body: B, encoding: Encoding) -> BoxedBody
+where
+    B: Body<Data = Bytes> + Send + Sync + Unpin + 'static,
+    B::Error: Into<BoxError>,
+{
+    let reader = body_reader(body);
+    match encoding {
+        Encoding::Brotli => reader_body(BrotliEncoder::new(reader)),
+        Encoding::Gzip => reader_body(GzipEncoder::new(reader)),
+        Encoding::Deflate => reader_body(ZlibEncoder::new(reader)),
+    }
+}
+
+pub fn decompress<B>(body: B, encoding: Encoding) -> BoxedBody
+where
+    B: Body<Data = Bytes> + Send + Sync + Unpin + 'static,
+    B::Error: Into<BoxError>,
+{
+    let reader = body_reader(body);
+    match encoding {
+        Encoding::Brotli => reader_body(BrotliDecoder::new(reader)),
+        Encoding::Gzip => reader_body(GzipDecoder::new(reader)),
+        Encoding::Deflate => reader_body(ZlibDecoder::new(reader)),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unsupported Content-Encoding: {0}")]
+pub struct UnsupportedEncoding(pub String);
+
+impl UnsupportedEncoding {
+    /// 415 (Unsupported Media Type) with the supported encodings
+    pub fn into_response(self) -> Response<BoxedBody> {
+        let mut response = Response::new(empty());
+        *response.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        let accepted = Encoding::ALL.map(|e| e.as_str()).join(", ");
+        if let Ok(value) = HeaderValue::from_str(&accepted) {
+            response.headers_mut().insert(ACCEPT_ENCODING, value);
+        }
+        response
+    }
+}
+
+/// The request body, decompressed according to its Content-Encoding
+///
+/// Note: size limits should be applied on the returned (decompressed) body (zip bombs)
+pub fn decoded_body<B>(req: Request<B>) -> Result<BoxedBody, UnsupportedEncoding>
+where
+    B: Body<Data = Bytes> + Send + Sync + Unpin + 'static,
+    B::Error: Into<BoxError>,
+{
+    let (parts, body) = req.into_parts();
+    let content_encoding = parts
+        .headers
+        .get(CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty() && !v.eq_ignore_ascii_case("identity"));
+    let Some(content_encoding) = content_encoding else {
+        return Ok(body.map_err(Into::into).boxed());
+    };
+    match Encoding::parse(content_encoding) {
+        Some(encoding) => Ok(decompress(body, encoding)),
+        None => Err(UnsupportedEncoding(content_encoding.to_string())),
+    }
+}
+
+/// Compress the responses (Accept-Encoding negotiation)
+///
+/// Not compressed: HEAD requests, responses without content (204, 304), partial content,
+/// responses already encoded, smaller than `min_size` (if the length is known) or
+/// with an already compressed content type (images, archives...) or event streams
+pub struct Compression {
+    pub min_size: u64,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+}
+
+fn is_compressible(headers: &HeaderMap, min_size: u64) -> bool {
+    if headers.contains_key(CONTENT_ENCODING) || headers.contains_key(CONTENT_RANGE) {
+        return false;
+    }
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if content_length.is_some_and(|len| len < min_size) {
+        return false;
+    }
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    // event streams: the encoder would buffer the events
+    !(content_type.starts_with("text/event-stream")
+        || content_type.starts_with("image/") && content_type != "image/svg+xml"
+        || content_type.starts_with("audio/")
+        || content_type.starts_with("video/")
+        || matches!(
+            content_type,
+            "application/gzip" | "application/zip" | "application/x-bzip2" | "application/x-xz"
+        ))
+}
+
+impl Middleware for Compression {
+    fn call(
+        &self,
+        req: Request<hyper::body::Incoming>,
+        params: Params,
+        next: Next,
+    ) -> HandlerFuture {
+        let encoding = req
+            .headers()
+            .get(ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .and_then(negotiate);
+        let head = req.method() == Method::HEAD;
+        let min_size = self.min_size;
+        Box::pin(async move {
+            let response = next.run(req, params).await?;
+            let (mut parts, body) = response.into_parts();
+            // the response depends on Accept-Encoding (caches)
+            parts
+                .headers
+                .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+            let Some(encoding) = encoding else {
+                return Ok(Response::from_parts(parts, body));
+            };
+            let no_content = matches!(
+                parts.status,
+                StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+            ) || parts.status.is_informational();
+            // Note: the body size hint is exact for full bodies (without Content-Length header yet)
+            let too_small = body.size_hint().exact().is_some_and(|len| len < min_size);
+            if head || no_content || too_small || !is_compressible(&parts.headers, min_size) {
+                return Ok(Response::from_parts(parts, body));
+            }
+
+            parts.headers.insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.as_str()),
+            );
+            // the compressed length is unknown (chunked / http2 data frames)
+            parts.headers.remove(CONTENT_LENGTH);
+            // a strong ETag identifies a byte representation: only a weak one now
+            if let Some(etag) = parts.headers.get(ETAG).and_then(|v| v.to_str().ok()) {
+                if !etag.starts_with("W/") {
+                    if let Ok(weak) = HeaderValue::from_str(&format!("W/{}", etag)) {
+                        parts.headers.insert(ETAG, weak);
+                    }
+                }
+            }
+            Ok(Response::from_parts(parts, compress(body, encoding)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::Full;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip;q=1.0, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate, gzip;q=0.8"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("br;q=0, *;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+        assert_eq!(negotiate("compress, zstd"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_compress_decompress() {
+        let data = Bytes::from("hello world! ".repeat(100));
+        for encoding in Encoding::ALL {
+            let compressed = compress(Full::new(data.clone()), encoding)
+                .collect()
+                .await
+                .unwrap()
+                .to_bytes();
+            assert!(compressed.len() < data.len(), "{:?}", encoding);
+            let decompressed = decompress(Full::new(compressed), encoding)
+                .collect()
+                .await
+                .unwrap()
+                .to_bytes();
+            assert_eq!(decompressed, data, "{:?}", encoding);
+        }
+    }
+}
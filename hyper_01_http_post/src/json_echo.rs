@@ -0,0 +1,306 @@
+//! POST /echo/json: parse (validate) a JSON or YAML body and send it back in the format
+//! negotiated with Accept
+//!
+//! * request format (Content-Type): application/json (default), application/yaml (or
+//!   application/x-yaml, text/yaml, *+json, *+yaml), else 415
+//! * response format (Accept, highest q-value): application/json (compact, pretty with a
+//!   `pretty` parameter), application/yaml, */* or no Accept: pretty JSON, else 406
+//! * invalid body: 400 with a JSON error (message, line & column)
+//!
+//! YAML is (de)serialized with serde_yaml (as in rust_crate_serde_yaml_01), through a
+//! serde_json Value: keys must be strings.
+
+use http_body_util::{BodyExt, LengthLimitError};
+use hyper::body::Incoming;
+use hyper::header::{HeaderValue, ACCEPT, CONTENT_TYPE, VARY};
+use hyper::{Request, Response, StatusCode};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::body::{full, limited};
+use crate::compression::decoded_body;
+use crate::router::{payload_too_large, HandlerResult};
+
+const JSON: &str = "application/json";
+const YAML: &str = "application/yaml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    PrettyJson,
+    Yaml,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json | Format::PrettyJson => JSON,
+            Format::Yaml => YAML,
+        }
+    }
+
+    /// Format of a request body (None: unsupported media type)
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match mime.as_str() {
+            "application/json" => Some(Format::Json),
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => {
+                Some(Format::Yaml)
+            }
+            m if m.ends_with("+json") => Some(Format::Json),
+            m if m.ends_with("+yaml") => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// An item of an Accept header (e.g. application/json;q=0.5)
+#[derive(Debug)]
+struct MediaRange {
+    mime: String,
+    params: Vec<(String, String)>,
+    q: f32,
+}
+
+/// Parse an Accept header
+fn parse_accept(value: &str) -> Vec<MediaRange> {
+    value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let mime = parts.next()?.trim().to_ascii_lowercase();
+            if mime.is_empty() {
+                return None;
+            }
+            let mut q = 1.0;
+            let mut params = vec![];
+            for param in parts {
+                let (name, value) = param.split_once('=').unwrap_or((param, ""));
+                let name = name.trim().to_ascii_lowercase();
+                let value = value.trim().trim_matches('"').to_ascii_lowercase();
+                match name.as_str() {
+                    "q" => q = value.parse::<f32>().unwrap_or(0.0),
+                    _ => params.push((name, value)),
+                }
+            }
+            Some(MediaRange { mime, params, q })
+        })
+        .collect()
+}
+
+/// Choose the response format: the supported media range with the highest q-value (the first
+/// one if q-values are equal), None if nothing acceptable
+pub fn negotiate(accept: Option<&str>) -> Option<Format> {
+    let Some(accept) = accept.filter(|a| !a.trim().is_empty()) else {
+        return Some(Format::PrettyJson);
+    };
+    let mut best: Option<(Format, f32)> = None;
+    for MediaRange { mime, params, q } in parse_accept(accept) {
+        let format = match mime.as_str() {
+            "application/json" => {
+                let pretty = params
+                    .iter()
+                    .any(|(name, value)| name == "pretty" && value != "false" && value != "0");
+                match pretty {
+                    true => Format::PrettyJson,
+                    false => Format::Json,
+                }
+            }
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => Format::Yaml,
+            // e.g. curl (*/*): human readable
+            "*/*" | "application/*" => Format::PrettyJson,
+            _ => continue,
+        };
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((format, q));
+        }
+    }
+    best.map(|(format, _)| format)
+}
+
+/// A parse error (or an unsupported format), sent as JSON
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ErrorMessage {
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl ErrorMessage {
+    fn new<S: Into<String>>(error: S) -> Self {
+        Self {
+            error: error.into(),
+            line: None,
+            column: None,
+        }
+    }
+
+    /// The position is in its own fields: removed from the message
+    fn at(message: String, line: usize, column: usize) -> Self {
+        let suffix = format!(" at line {} column {}", line, column);
+        Self {
+            error: message
+                .strip_suffix(&suffix)
+                .map(|m| m.to_string())
+                .unwrap_or(message),
+            line: Some(line),
+            column: Some(column),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ErrorMessage {
+    fn from(e: serde_json::Error) -> Self {
+        Self::at(e.to_string(), e.line(), e.column())
+    }
+}
+
+impl From<serde_yaml::Error> for ErrorMessage {
+    fn from(e: serde_yaml::Error) -> Self {
+        match e.location() {
+            Some(location) => Self::at(e.to_string(), location.line(), location.column()),
+            None => Self::new(e.to_string()),
+        }
+    }
+}
+
+pub fn parse(body: &[u8], format: Format) -> Result<Value, ErrorMessage> {
+    match format {
+        Format::Json | Format::PrettyJson => Ok(serde_json::from_slice(body)?),
+        Format::Yaml => Ok(serde_yaml::from_slice(body)?),
+    }
+}
+
+pub fn serialize(value: &Value, format: Format) -> Result<Vec<u8>, ErrorMessage> {
+    match format {
+        Format::Json => Ok(serde_json::to_vec(value)?),
+        Format::PrettyJson => {
+            let mut out = serde_json::to_vec_pretty(value)?;
+            out.push(b'\n');
+            Ok(out)
+        }
+        Format::Yaml => Ok(serde_yaml::to_string(value)?.into_bytes()),
+    }
+}
+
+fn error_response(status: StatusCode, error: &ErrorMessage) -> HandlerResult {
+    let mut response = Response::new(full(serde_json::to_vec_pretty(error)?));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(JSON));
+    Ok(response)
+}
+
+/// POST /echo/json (the body is read in memory, at most `max_size` bytes)
+pub async fn handle(req: Request<Incoming>, max_size: u64) -> HandlerResult {
+    let headers = req.headers();
+    let input = match headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+        None => Format::Json,
+        Some(content_type) => match Format::from_content_type(content_type) {
+            Some(format) => format,
+            None => {
+                return error_response(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    &ErrorMessage::new(format!(
+                        "Unsupported Content-Type: {} (expected {} or {})",
+                        content_type, JSON, YAML
+                    )),
+                )
+            }
+        },
+    };
+    let Some(output) = negotiate(headers.get(ACCEPT).and_then(|v| v.to_str().ok())) else {
+        return error_response(
+            StatusCode::NOT_ACCEPTABLE,
+            &ErrorMessage::new(format!("Acceptable response formats: {}, {}", JSON, YAML)),
+        );
+    };
+
+    let body = match decoded_body(req) {
+        Ok(body) => body,
+        Err(e) => return Ok(e.into_response()),
+    };
+    let body = match limited(body, max_size).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) if e.is::<LengthLimitError>() => return Ok(payload_too_large(max_size)),
+        Err(e) => return Err(e),
+    };
+
+    let value = match parse(&body, input) {
+        Ok(value) => value,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e),
+    };
+    let mut response = Response::new(full(serialize(&value, output).map_err(|e| e.error)?));
+    let headers = response.headers_mut();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(output.content_type()),
+    );
+    headers.insert(VARY, HeaderValue::from_static("accept"));
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(None), Some(Format::PrettyJson));
+        assert_eq!(negotiate(Some("*/*")), Some(Format::PrettyJson));
+        assert_eq!(negotiate(Some("application/json")), Some(Format::Json));
+        assert_eq!(
+            negotiate(Some("application/json; pretty")),
+            Some(Format::PrettyJson)
+        );
+        assert_eq!(
+            negotiate(Some("application/json;q=0.5, application/yaml")),
+            Some(Format::Yaml)
+        );
+        assert_eq!(
+            negotiate(Some("text/html, */*;q=0.1")),
+            Some(Format::PrettyJson)
+        );
+        assert_eq!(negotiate(Some("text/html")), None);
+        assert_eq!(negotiate(Some("application/json;q=0")), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = parse(b"{\n  \"library\": hyper\n}", Format::Json).unwrap_err();
+        assert_eq!(
+            error,
+            ErrorMessage {
+                error: "expected value".to_string(),
+                line: Some(2),
+                column: Some(14),
+            }
+        );
+
+        let error = parse(b"library: hyper\n  version: 1\n", Format::Yaml).unwrap_err();
+        assert_eq!(error.line, Some(2));
+        assert_eq!(error.column, Some(10));
+        assert!(!error.error.contains("at line"), "{}", error.error);
+    }
+
+    #[test]
+    fn test_yaml_to_json() {
+        let value = parse(b"library: hyper\nversions: [0.14, 1]\n", Format::Yaml).unwrap();
+        assert_eq!(
+            serialize(&value, Format::Json).unwrap(),
+            br#"{"library":"hyper","versions":[0.14,1]}"#
+        );
+        assert_eq!(
+            serialize(&value, Format::Yaml).unwrap(),
+            b"library: hyper\nversions:\n- 0.14\n- 1\n"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Key value REST API backed by the Datastore of rust_30_deref
+//!
+//! * PUT /kv/:key: store the request body (raw bytes) & its Content-Type (201 or 204 if replaced)
+//! * GET /kv/:key: the value with its Content-Type (404 if missing)
+//! * HEAD /kv/:key: the value size (Content-Length) without body
+//! * DELETE /kv/:key: 204 (404 if missing)
+//! * GET /kv?prefix=...: keys starting with prefix (one per line, percent encoded)
+//!
+//! Keys are path segments: percent decoded before use (e.g. /kv/a%2Fb is the key "a/b")
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, LengthLimitError};
+use hyper::body::Incoming;
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Method, Request, Response, StatusCode};
+use percent_encoding::{percent_decode_str, percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rust_30_deref::Datastore;
+
+use crate::body::{empty, full, limited};
+use crate::compression::decoded_body;
+use crate::router::{payload_too_large, HandlerResult, Params};
+
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+/// Characters encoded in the listed keys: all but unreserved characters
+const KEY_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A stored value: raw bytes (in the Datastore) & content type
+#[derive(Debug, Default)]
+pub struct KvStore {
+    values: Datastore,
+    content_types: BTreeMap<Vec<u8>, String>,
+}
+
+impl KvStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<(Bytes, &str)> {
+        let value = self.values.get(key)?;
+        let content_type = self
+            .content_types
+            .get(key)
+            .map(|c| c.as_str())
+            .unwrap_or(DEFAULT_CONTENT_TYPE);
+        Some((Bytes::copy_from_slice(value), content_type))
+    }
+
+    pub fn size(&self, key: &[u8]) -> Option<usize> {
+        self.values.get(key).map(|v| v.len())
+    }
+
+    /// Store a value, return true if a value was replaced
+    pub fn put(&mut self, key: Vec<u8>, value: Vec<u8>, content_type: Option<String>) -> bool {
+        match content_type {
+            Some(content_type) => self.content_types.insert(key.clone(), content_type),
+            None => self.content_types.remove(&key),
+        };
+        self.values.insert(key, value).is_some()
+    }
+
+    /// Remove a value, return true if it existed
+    pub fn delete(&mut self, key: &[u8]) -> bool {
+        self.content_types.remove(key);
+        self.values.remove(key).is_some()
+    }
+
+    /// Keys starting with `prefix` (in key order)
+    pub fn keys(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+        self.values
+            .range(prefix.to_vec()..)
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+}
+
+pub type SharedKvStore = Arc<RwLock<KvStore>>;
+
+fn status(status: StatusCode) -> HandlerResult {
+    let mut response = Response::new(empty());
+    *response.status_mut() = status;
+    Ok(response)
+}
+
+fn decode_key(params: &Params) -> Option<Vec<u8>> {
+    let key: Vec<u8> = percent_decode_str(params.get("key")?).collect();
+    (!key.is_empty()).then_some(key)
+}
+
+/// GET /kv?prefix=...
+pub async fn list(req: Request<Incoming>, store: SharedKvStore) -> HandlerResult {
+    let prefix: Vec<u8> = req
+        .uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|p| p.strip_prefix("prefix="))
+        // '+' is a space in a query string
+        .map(|p| percent_decode_str(&p.replace('+', " ")).collect())
+        .unwrap_or_default();
+    let keys = store.read().unwrap().keys(&prefix);
+    let listing: String = keys
+        .iter()
+        .map(|k| format!("{}\n", percent_encode(k, KEY_SEGMENT)))
+        .collect();
+    let mut response = Response::new(full(listing));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    Ok(response)
+}
+
+/// GET|HEAD /kv/:key
+pub async fn get(req: Request<Incoming>, params: Params, store: SharedKvStore) -> HandlerResult {
+    let Some(key) = decode_key(&params) else {
+        return status(StatusCode::BAD_REQUEST);
+    };
+    let store = store.read().unwrap();
+    if req.method() == Method::HEAD {
+        let Some(size) = store.size(&key) else {
+            return status(StatusCode::NOT_FOUND);
+        };
+        let mut response = Response::new(empty());
+        response
+            .headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(size));
+        return Ok(response);
+    }
+    let Some((value, content_type)) = store.get(&key) else {
+        return status(StatusCode::NOT_FOUND);
+    };
+    let mut response = Response::new(full(value));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_str(content_type)?);
+    Ok(response)
+}
+
+/// PUT /kv/:key (the value is read in memory, at most `max_size` bytes)
+pub async fn put(
+    req: Request<Incoming>,
+    params: Params,
+    store: SharedKvStore,
+    max_size: u64,
+) -> HandlerResult {
+    let Some(key) = decode_key(&params) else {
+        return status(StatusCode::BAD_REQUEST);
+    };
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    // the value is stored decompressed (if sent with a Content-Encoding)
+    let body = match decoded_body(req) {
+        Ok(body) => body,
+        Err(e) => return Ok(e.into_response()),
+    };
+    let value = match limited(body, max_size).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) if e.is::<LengthLimitError>() => return Ok(payload_too_large(max_size)),
+        Err(e) => return Err(e),
+    };
+    match store
+        .write()
+        .unwrap()
+        .put(key, value.to_vec(), content_type)
+    {
+        true => status(StatusCode::NO_CONTENT),
+        false => status(StatusCode::CREATED),
+    }
+}
+
+/// DELETE /kv/:key
+pub async fn delete(params: Params, store: SharedKvStore) -> HandlerResult {
+    let Some(key) = decode_key(&params) else {
+        return status(StatusCode::BAD_REQUEST);
+    };
+    match store.write().unwrap().delete(&key) {
+        true => status(StatusCode::NO_CONTENT),
+        false => status(StatusCode::NOT_FOUND),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kv_store() {
+        let mut store = KvStore::new();
+        assert!(!store.put(
+            b"user/1".to_vec(),
+            b"alice".to_vec(),
+            Some("text/plain".into())
+        ));
+        assert!(!store.put(b"user/2".to_vec(), vec![0, 1, 2], None));
+        assert!(!store.put(b"users".to_vec(), b"{}".to_vec(), None));
+
+        assert_eq!(
+            store.get(b"user/1"),
+            Some((Bytes::from("alice"), "text/plain"))
+        );
+        assert_eq!(
+            store.get(b"user/2"),
+            Some((Bytes::from(vec![0, 1, 2]), DEFAULT_CONTENT_TYPE))
+        );
+        assert_eq!(store.size(b"user/2"), Some(3));
+        assert_eq!(
+            store.keys(b"user/"),
+            vec![b"user/1".to_vec(), b"user/2".to_vec()]
+        );
+        assert_eq!(store.keys(b"").len(), 3);
+
+        // replaced: the content type is replaced too
+        assert!(store.put(b"user/1".to_vec(), b"bob".to_vec(), None));
+        assert_eq!(store.get(b"user/1").unwrap().1, DEFAULT_CONTENT_TYPE);
+
+        assert!(store.delete(b"user/1"));
+        assert!(!store.delete(b"user/1"));
+        assert_eq!(store.get(b"user/1"), None);
+    }
+}
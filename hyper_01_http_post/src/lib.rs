@@ -0,0 +1,33 @@
+//! Building blocks of the hyper echo server (see main.rs & server.rs)
+//!
+//! * access_log: request ids & access log (Common / Combined Log Format)
+//! * body: boxed response body & helpers
+//! * client: http client with timeouts, retries & connection pool settings
+//! * compression: response compression (Accept-Encoding) & request body decompression
+//! * json_echo: JSON / YAML echo (validation, format negotiated with Accept)
+//! * kv: key value REST API (backed by the Datastore of rust_30_deref)
+//! * metrics: request counts & latency histograms (Prometheus text format)
+//! * proxy: reverse proxy & load balancer (round-robin / least-connections, health checks)
+//! * router: routing table (method + path pattern) with path parameters & middlewares
+//! * server: the echo server routes, connections (tcp / tls) & graceful shutdown
+//! * shutdown: os signals & open connections tracking
+//! * sse: Server-Sent Events response (any Stream of events)
+//! * static_files: serve a directory (Range, ETag, conditional requests, listing)
+//! * upload: multipart/form-data uploads (streamed to a directory, JSON summary)
+//! * websocket: WebSocket upgrade & echo session
+
+pub mod access_log;
+pub mod body;
+pub mod client;
+pub mod compression;
+pub mod json_echo;
+pub mod kv;
+pub mod metrics;
+pub mod proxy;
+pub mod router;
+pub mod server;
+pub mod shutdown;
+pub mod sse;
+pub mod static_files;
+pub mod upload;
+pub mod websocket;
@@ -1,12 +1,20 @@
 // use std::convert::Infallible;
 use std::net::SocketAddr;
-
-use hyper::{Body, Request, Response, Server};
-use hyper::service::{make_service_fn, service_fn};
-use hyper::{Method, StatusCode};
-use futures::TryStreamExt as _;
-use tokio::sync::mpsc;
-use tokio::sync::mpsc::{Sender, Receiver};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::Uri;
+use tokio::net::TcpListener;
+
+use hyper_01_http_post::access_log::LogFormat;
+use hyper_01_http_post::compression::Compression;
+use hyper_01_http_post::proxy::{Balancing, Proxy};
+use hyper_01_http_post::server::{Server, ServerConfig};
+use hyper_01_http_post::shutdown::os_signal;
+use hyper_01_http_post::static_files::StaticFiles;
+use hyper_01_http_post::upload::Uploads;
+use tls_config_lib::{parse_args, TlsServerBuilder};
 
 /*
  * cargo run
@@ -15,160 +23,180 @@ use tokio::sync::mpsc::{Sender, Receiver};
  * curl http://127.0.0.1:8000/echo/uppercase -X POST -d 'hello world'
  * curl http://127.0.0.1:8000/echo/reversed -X POST -d 'hello world'
  *
- * STOP the server
- * curl http://127.0.0.1:8000/stop -X POST -d ''  // will send false to mpsc
- * curl http://127.0.0.1:8000/stop -X POST -d '1' // will send true to mpsc
+ * JSON / YAML echo (parsed & validated, response format negotiated with Accept):
+ * curl http://127.0.0.1:8000/echo/json -H 'Content-Type: application/json' -d '{"library":"hyper"}'
+ * curl http://127.0.0.1:8000/echo/json -H 'Content-Type: application/json' -H 'Accept: application/yaml' -d '{"library":"hyper"}'
+ * curl http://127.0.0.1:8000/echo/json -H 'Content-Type: application/yaml' -H 'Accept: application/json' --data-binary $'library: hyper\nversion: 1\n'
+ *
+ * Routes are registered in make_router (server.rs, see router.rs for path patterns & middlewares)
+ *
+ * Request body limits (413 Payload Too Large beyond max body size):
+ * cargo run -- --max-body-size 10485760 --spill-threshold 1048576 [--spill-dir /tmp]
+ *
+ * HTTPS (http2 or http1.1 negotiated with ALPN), using the certificates of tokio_tcp_tls
+ * (see tokio_tcp_tls/certs/ca_signed.sh):
+ * cargo run -- --cert ../tokio_tcp_tls/certs/ca_signed/mydomain.com.crt --key ../tokio_tcp_tls/certs/ca_signed/mydomain.com.key [--tls-addr 127.0.0.1:8443]
+ * curl --http2 --cacert ../tokio_tcp_tls/certs/ca_signed/root_ca.pem --resolve mydomain.com:8443:127.0.0.1 https://mydomain.com:8443/echo -X POST -d 'hello world'
+ *
+ * HTTP2 without TLS (h2c, prior knowledge only: no Upgrade from http1.1) on the plain port:
+ * cargo run -- --h2c
+ * curl --http2-prior-knowledge http://127.0.0.1:8000/echo -X POST -d 'hello world'
+ *
+ * Static files (GET / HEAD, Range & conditional requests supported):
+ * cargo run -- --static-dir ./ [--dir-listing]
+ * curl http://127.0.0.1:8000/static/Cargo.toml -H 'Range: bytes=0-9'
+ *
+ * Response compression (gzip, deflate, br) negotiated with Accept-Encoding, gzip request bodies:
+ * cargo run -- [--compress-min-size 1024 | --compress-min-size off]
+ * curl --compressed http://127.0.0.1:8000/echo/uppercase -X POST -d 'hello world' -v
+ * echo 'hello world' | gzip | curl http://127.0.0.1:8000/echo/reversed -H 'Content-Encoding: gzip' --data-binary @-
+ *
+ * WebSocket echo (text uppercased, binary reversed, "bye" to close):
+ * cargo run --example 03_client -- ws://127.0.0.1:8000/ws
+ *
+ * Reverse proxy / load balancer (all requests but /metrics are forwarded to the upstreams,
+ * round-robin or least-connections, unhealthy upstreams are skipped until they recover):
+ * cargo run -- --addr 127.0.0.1:8001 & cargo run -- --addr 127.0.0.1:8002 &
+ * cargo run -- --upstreams http://127.0.0.1:8001,http://127.0.0.1:8002 [--lb least-connections] [--health-check-path /] [--health-check-interval-ms 5000] [--upstream-timeout-ms 30000]
+ * curl http://127.0.0.1:8000/echo -X POST -d 'hello world' -v
+ *
+ * Uploads (multipart/form-data, files written to the upload dir, JSON summary with SHA-256):
+ * cargo run -- --upload-dir /tmp/uploads [--max-upload-part-size 4194304] [--max-upload-size 10485760]
+ * curl http://127.0.0.1:8000/upload -F title=hello -F file=@Cargo.toml
+ *
+ * Server-Sent Events (one event per second, resumed after the Last-Event-ID if sent):
+ * curl -N http://127.0.0.1:8000/events [-H 'Last-Event-ID: 42']
+ *
+ * Key value store (values stored as raw bytes with their content type):
+ * curl http://127.0.0.1:8000/kv/greeting -X PUT -H 'Content-Type: text/plain' -d 'hello world'
+ * curl http://127.0.0.1:8000/kv/greeting [-I]
+ * curl 'http://127.0.0.1:8000/kv?prefix=gr'
+ * curl http://127.0.0.1:8000/kv/greeting -X DELETE
+ *
+ * Access log (Combined Log Format by default) & request ids (X-Request-Id, generated if missing):
+ * cargo run -- [--log-format common]
+ * curl http://127.0.0.1:8000/echo -X POST -d 'hello world' -H 'X-Request-Id: my-request-42' -v
+ *
+ * Prometheus metrics (request counts & latency histograms by method, route & status):
+ * curl http://127.0.0.1:8000/metrics
+ *
+ * STOP the server: Ctrl-C / SIGTERM or (if HYPER_ECHO_ADMIN_TOKEN is set):
+ * HYPER_ECHO_ADMIN_TOKEN=s3cret cargo run -- [--drain-timeout-secs 10]
+ * curl http://127.0.0.1:8000/stop -H 'Authorization: Bearer s3cret' -X POST -d ''  // will send false to mpsc
+ * curl http://127.0.0.1:8000/stop -H 'Authorization: Bearer s3cret' -X POST -d '1' // will send true to mpsc
  */
 
-
-async fn echo(req: Request<Body>, tx: Sender<bool>) -> Result<Response<Body>, hyper::Error> {
-    println!("Handling a connection...");
-    // Ok(Response::new("Hello, World".into()))
-
-    let mut response = Response::new(Body::empty());
-
-    match (req.method(), req.uri().path()) {
-
-        (&Method::GET, "/") => {
-            *response.body_mut() = Body::from(
-                "Try POST'ing data to /echo, e.g.: curl http://127.0.0.1:3000/echo -X POST -d 'hello world'");
-        },
-
-        (&Method::POST, "/stop") => {
-            println!("[echo] Got stop...");
-
-            let full_body: hyper::body::Bytes = hyper::body::to_bytes(req.into_body()).await?;
-
-            // tx.send(false).await;
-            let to_send: bool = match full_body.is_empty() {
-                true => false,
-                false => true,
-            };
-
-            if let Err(e) = tx.send(to_send).await {
-                eprintln!("Unable to send to channel: {}", e);
-            }
-
-            // Response::new("Thanks for stopping the server...".into()))
-            *response.body_mut() = Body::from("Thanks for that!! Will stop the server...");
-        },
-
-        (&Method::POST, "/echo") => {
-            // just echo back what was send
-            *response.body_mut() = req.into_body();
-        },
-
-        (&Method::POST, "/echo/uppercase") => {
-            let mapping = req
-                .into_body()
-                .map_ok(|chunk| {
-                    chunk
-                        .iter()
-                        .map(|byte| byte.to_ascii_uppercase())
-                        .collect::<Vec<u8>>()
-                });
-            *response.body_mut() = Body::wrap_stream(mapping);
-        },
-
-        (&Method::POST, "/echo/reversed") => {
-            let full_body: hyper::body::Bytes = hyper::body::to_bytes(req.into_body()).await?;
-            println!("full_body: {:?}", full_body);
-            // iter() -> iterator over the slice
-            // rev() -> (aka std::iter::Rev): reversed iterator
-            // cloned() -> (aka std::iter::Cloned) iterator that clone the underlying iterator
-            let reversed: Vec<u8> = full_body
-                .iter()
-                .rev()
-                .cloned()
-                .collect::<Vec<u8>>();
-            *response.body_mut() = reversed.into();
-        },
-
-        _ => {
-            *response.status_mut() = StatusCode::NOT_FOUND;
+const ADMIN_TOKEN_ENV: &str = "HYPER_ECHO_ADMIN_TOKEN";
+const DEFAULT_ADDR: &str = "127.0.0.1:8000";
+const DEFAULT_TLS_ADDR: &str = "127.0.0.1:8443";
+
+async fn app_main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (_, options) = parse_args();
+    let addr: SocketAddr = options
+        .get("addr")
+        .map(|a| a.as_str())
+        .unwrap_or(DEFAULT_ADDR)
+        .parse()?;
+
+    let mut config = ServerConfig::default();
+    if let Some(max_size) = options.get("max-body-size") {
+        config.limits.max_size = max_size.parse()?;
+    }
+    if let Some(threshold) = options.get("spill-threshold") {
+        config.limits.spill_threshold = threshold.parse()?;
+    }
+    if let Some(dir) = options.get("spill-dir") {
+        config.limits.spill_dir = PathBuf::from(dir);
+    }
+    if let Some(secs) = options.get("drain-timeout-secs") {
+        config.drain_timeout = Duration::from_secs(secs.parse()?);
+    }
+    if let Some(dir) = options.get("upload-dir") {
+        let mut uploads = Uploads::new(dir);
+        if let Some(size) = options.get("max-upload-part-size") {
+            uploads = uploads.max_part_size(size.parse()?);
         }
-
-    };
-
-    Ok(response)
-
-}
-
-async fn shutdown_signal() {
-
-    // Wait for Ctrl-C signal
-    tokio::signal::ctrl_c()
-        .await
-        .expect("failed to install CTRL+C signal handler");
-}
-
-async fn wait_for_stop_true(mut rx: Receiver<bool>) {
-
-    loop {
-        let stop = rx.recv().await;
-        println!("Got a stop value: {:?}", stop);
-        match stop {
-            Some(stop_) if stop_ == true => break,
-            _  => continue,
+        if let Some(size) = options.get("max-upload-size") {
+            uploads = uploads.max_total_size(size.parse()?);
         }
+        tokio::fs::create_dir_all(uploads.dir()).await?;
+        config.uploads = Some(uploads);
     }
-}
-
-async fn shutdown_from_channel(rx: Receiver<bool>) {
-
-    // as an exercise, use a oneshot channel (triggered by HTTP POST to url: /stop)
-    // to stop the server
-
-    // rx.recv().await;
-    wait_for_stop_true(rx).await;
-}
-
-
-async fn app_main() {
-
-    let addr = SocketAddr::from(([127, 0, 0, 1], 8000));
-    // For every connection, make a Service to handle all incoming HTTP requests on said connection
-
-    /*
-    let make_svc = make_service_fn(|_conn| async {
-
-        // 'service_fn' is a helper to convert a function that return a Response into a 'Service'
-        Ok::<_, Infallible>(service_fn(echo))
-    });
-    */
-
-    let (tx, rx) = mpsc::channel(1);
-
-    let make_svc = make_service_fn(move |_conn| {
-        // move tx to closure
-        // closure can be called multiple times (so we clone)
-        let tx = tx.clone();
-        async move {
-            // async block is only exec once
-            // so move tx to the closure
-            Ok::<_, hyper::Error>(service_fn(
-                move |req| {
-                    // this closure is also be called multiple times so make a clone too
-                    // and move the clone into the async block
-                    let tx = tx.clone();
-                    async move { echo(req, tx).await }
-                }
-            ))
+    if let Some(upstreams) = options.get("upstreams") {
+        let upstreams = upstreams
+            .split(',')
+            .map(|u| u.trim().parse::<Uri>())
+            .collect::<Result<Vec<_>, _>>()?;
+        let balancing: Balancing = match options.get("lb") {
+            Some(lb) => lb.parse()?,
+            None => Balancing::RoundRobin,
+        };
+        let mut proxy = Proxy::new(&upstreams, balancing)?;
+        if let Some(path) = options.get("health-check-path") {
+            proxy = proxy.health_check_path(path.as_str());
         }
-    });
-
-    let server = Server::bind(&addr).serve(make_svc);
-    // let graceful = server.with_graceful_shutdown(shutdown_signal());
-    let graceful = server.with_graceful_shutdown(shutdown_from_channel(rx));
-
-    println!("Listening on http://{}", addr);
-
-    if let Err(e) = graceful.await {
-        eprintln!("Server error: {}", e);
+        if let Some(ms) = options.get("upstream-timeout-ms") {
+            proxy = proxy.request_timeout(Duration::from_millis(ms.parse()?));
+        }
+        if let Some(ms) = options.get("health-check-interval-ms") {
+            proxy = proxy.health_check_interval(Duration::from_millis(ms.parse()?));
+        }
+        println!("Proxy mode ({:?}), upstreams: {:?}", balancing, upstreams);
+        let proxy = Arc::new(proxy);
+        tokio::spawn(proxy.clone().health_checks());
+        config.proxy = Some(proxy);
     }
+    config.admin_token = std::env::var(ADMIN_TOKEN_ENV)
+        .ok()
+        .filter(|t| !t.is_empty());
+    if config.admin_token.is_none() {
+        println!("{} is not set, /stop is disabled", ADMIN_TOKEN_ENV);
+    }
+    config.compression_min_size = match options.get("compress-min-size") {
+        Some(size) if size == "off" => None,
+        Some(size) => Some(size.parse()?),
+        None => Some(Compression::default().min_size),
+    };
+    config.log_format = match options.get("log-format").map(|f| f.as_str()) {
+        Some("common") => LogFormat::Common,
+        Some("combined") | None => LogFormat::Combined,
+        Some(format) => return Err(format!("Unknown log format: {}", format).into()),
+    };
+    config.static_files = options
+        .get("static-dir")
+        .map(|dir| StaticFiles::new(dir).listing(options.contains_key("dir-listing")));
+    config.h2c = options.contains_key("h2c");
+    println!(
+        "body limits: {:?}, drain timeout: {:?}",
+        config.limits, config.drain_timeout
+    );
+
+    let mut server = Server::bind(addr, config).await?;
+    match (options.get("cert"), options.get("key")) {
+        (Some(cert), Some(key)) => {
+            let acceptor = TlsServerBuilder::from_pem_files(cert, key)
+                .alpn(&["h2", "http/1.1"])
+                .build_acceptor()?;
+            let tls_addr = options
+                .get("tls-addr")
+                .map(|a| a.as_str())
+                .unwrap_or(DEFAULT_TLS_ADDR);
+            let listener = TcpListener::bind(tls_addr).await?;
+            println!("Listening on https://{} (alpn: h2, http/1.1)", tls_addr);
+            server = server.tls(listener, acceptor);
+        }
+        (None, None) => {}
+        _ => return Err("Both --cert and --key are required for https".into()),
+    }
+
+    // Ctrl-C / SIGTERM, POST /stop is handled by the server
+    server.run(os_signal()).await;
+    Ok(())
 }
 
 fn main() {
     let rt = tokio::runtime::Runtime::new().unwrap();
-    rt.block_on(app_main());
+    if let Err(e) = rt.block_on(app_main()) {
+        eprintln!("Server error: {}", e);
+    }
 }
@@ -0,0 +1,236 @@
+//! Request metrics (counts & latency histograms by method, route & status) exposed in the
+//! Prometheus text format
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::body::Incoming;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Method, Request, Response};
+
+use crate::body::full;
+use crate::router::{HandlerFuture, HandlerResult, MatchedPath, Middleware, Next, Params};
+
+/// Latency histogram buckets (upper bounds, in seconds)
+const BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+/// Label of the requests without a matching route (404 / 405): the path is not used as a
+/// label (unbounded number of series)
+const UNMATCHED_ROUTE: &str = "unmatched";
+/// Label of the extension methods (chosen by the client: unbounded number of series)
+const OTHER_METHOD: &str = "other";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Labels {
+    method: String,
+    route: String,
+    status: u16,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Non cumulative counts (one per bucket, the last one is +Inf)
+    buckets: [u64; BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let index = BUCKETS
+            .iter()
+            .position(|le| seconds <= *le)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[index] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Metrics registry, shared by the middleware & the /metrics handler
+#[derive(Debug, Default)]
+pub struct Metrics {
+    series: Mutex<BTreeMap<Labels, Histogram>>,
+}
+
+/// Method label: the standard methods, "other" for the extension methods
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => OTHER_METHOD,
+    }
+}
+
+/// Escape a label value (Prometheus text format)
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&self, method: &str, route: &str, status: u16, latency: Duration) {
+        let labels = Labels {
+            method: method.to_string(),
+            route: route.to_string(),
+            status,
+        };
+        let mut series = self.series.lock().unwrap();
+        series
+            .entry(labels)
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    /// Render all the series in the Prometheus text format (version 0.0.4)
+    pub fn render(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP http_requests_total Total number of http requests.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for (labels, histogram) in series.iter() {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                escape(&labels.method),
+                escape(&labels.route),
+                labels.status,
+                histogram.count
+            );
+        }
+
+        out.push_str(
+            "# HELP http_request_duration_seconds Time to produce the response headers.\n",
+        );
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for (labels, histogram) in series.iter() {
+            let labels = format!(
+                "method=\"{}\",route=\"{}\",status=\"{}\"",
+                escape(&labels.method),
+                escape(&labels.route),
+                labels.status
+            );
+            let mut cumulative = 0;
+            for (i, count) in histogram.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = match BUCKETS.get(i) {
+                    Some(le) => le.to_string(),
+                    None => "+Inf".to_string(),
+                };
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+        out
+    }
+}
+
+/// Record a request count & latency (time to produce the response headers) per method,
+/// route pattern & status
+pub struct RecordMetrics(pub Arc<Metrics>);
+
+impl Middleware for RecordMetrics {
+    fn call(&self, req: Request<Incoming>, params: Params, next: Next) -> HandlerFuture {
+        let metrics = self.0.clone();
+        let method = method_label(req.method());
+        let route = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|p| p.0.clone())
+            .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+        Box::pin(async move {
+            let start = Instant::now();
+            let response = next.run(req, params).await;
+            // a handler error is turned into a 500 by the router
+            let status = match &response {
+                Ok(r) => r.status().as_u16(),
+                Err(_) => 500,
+            };
+            metrics.observe(method, &route, status, start.elapsed());
+            response
+        })
+    }
+}
+
+/// GET /metrics
+pub async fn metrics_handler(metrics: Arc<Metrics>) -> HandlerResult {
+    let mut response = Response::new(full(metrics.render()));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"),
+    );
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.observe("GET", "/", 200, Duration::from_millis(2));
+        metrics.observe("GET", "/", 200, Duration::from_millis(20));
+        metrics.observe("POST", "/echo/:transform", 413, Duration::from_secs(10));
+
+        let text = metrics.render();
+        assert!(text.contains("http_requests_total{method=\"GET\",route=\"/\",status=\"200\"} 2\n"));
+        assert!(text.contains(
+            "http_request_duration_seconds_bucket{method=\"GET\",route=\"/\",status=\"200\",le=\"0.001\"} 0\n"
+        ));
+        assert!(text.contains(
+            "http_request_duration_seconds_bucket{method=\"GET\",route=\"/\",status=\"200\",le=\"0.0025\"} 1\n"
+        ));
+        assert!(text.contains(
+            "http_request_duration_seconds_bucket{method=\"GET\",route=\"/\",status=\"200\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(text.contains(
+            "http_request_duration_seconds_bucket{method=\"POST\",route=\"/echo/:transform\",status=\"413\",le=\"5\"} 0\n"
+        ));
+        assert!(text.contains(
+            "http_request_duration_seconds_count{method=\"POST\",route=\"/echo/:transform\",status=\"413\"} 1\n"
+        ));
+    }
+
+    #[test]
+    fn test_method_label() {
+        assert_eq!(method_label(&Method::PATCH), "PATCH");
+        let purge = Method::from_bytes(b"PURGE").unwrap();
+        assert_eq!(method_label(&purge), OTHER_METHOD);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
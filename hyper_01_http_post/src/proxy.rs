@@ -0,0 +1,421 @@
+//! Reverse proxy & load balancer: requests are forwarded to a pool of upstream http servers
+//!
+//! * upstream selection: round-robin or least-connections (in flight requests)
+//! * hop-by-hop headers are stripped (request & response), X-Forwarded-For / X-Forwarded-Host
+//!   are added
+//! * active health checks: an upstream is removed from the pool when its health check fails
+//!   and re-added when it succeeds again
+//!
+//! Bodies are streamed (request & response), never buffered by the proxy.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use http_body_util::BodyExt;
+use hyper::body::Incoming;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONNECTION, HOST};
+use hyper::{Request, Response, StatusCode, Uri, Version};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioTimer};
+
+use crate::access_log::RemoteAddr;
+use crate::body::{empty, BoxError, BoxedBody};
+use crate::client::{HttpClient, DEFAULT_REQUEST_TIMEOUT};
+use crate::router::HandlerResult;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_HEALTH_CHECK_PATH: &str = "/";
+const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+/// Hop-by-hop headers (RFC 9110): only meaningful for a single connection
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Balancing {
+    RoundRobin,
+    LeastConnections,
+}
+
+impl std::str::FromStr for Balancing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(Balancing::RoundRobin),
+            "least-connections" => Ok(Balancing::LeastConnections),
+            _ => Err(format!("Unknown load balancing: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Upstream {
+    /// scheme & authority (e.g. http://127.0.0.1:8001)
+    uri: Uri,
+    healthy: AtomicBool,
+    /// In flight requests (until the end of the response body)
+    active: AtomicUsize,
+}
+
+impl Upstream {
+    fn new(uri: Uri) -> Self {
+        Self {
+            uri,
+            healthy: AtomicBool::new(true),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+}
+
+/// Decrement the in flight requests of an upstream on drop
+struct ActiveGuard(Arc<Upstream>);
+
+impl ActiveGuard {
+    fn new(upstream: Arc<Upstream>) -> Self {
+        upstream.active.fetch_add(1, Ordering::Relaxed);
+        Self(upstream)
+    }
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub struct Proxy {
+    upstreams: Vec<Arc<Upstream>>,
+    balancing: Balancing,
+    next: AtomicUsize,
+    client: Client<HttpConnector, BoxedBody>,
+    /// Max time to get the upstream response headers
+    request_timeout: Duration,
+    health_check_path: String,
+    health_check_interval: Duration,
+}
+
+/// Remove the hop-by-hop headers, including the ones listed in the Connection header
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(name);
+    }
+}
+
+/// Append the client ip to X-Forwarded-For (a list: client, proxy 1, proxy 2...)
+///
+/// Note: the list can be split in several header lines, they are joined in one
+fn append_forwarded_for(headers: &mut HeaderMap, client_ip: IpAddr) {
+    let mut hops: Vec<&str> = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+    let client_ip = client_ip.to_string();
+    hops.push(&client_ip);
+    let value = hops.join(", ");
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        headers.insert(X_FORWARDED_FOR, value);
+    }
+}
+
+fn status(status: StatusCode) -> HandlerResult {
+    let mut response = Response::new(empty());
+    *response.status_mut() = status;
+    Ok(response)
+}
+
+impl Proxy {
+    /// Upstream uris: http://host:port (a path, if any, is ignored)
+    pub fn new(upstreams: &[Uri], balancing: Balancing) -> Result<Self, BoxError> {
+        if upstreams.is_empty() {
+            return Err("No upstream".into());
+        }
+        let upstreams = upstreams
+            .iter()
+            .map(|uri| {
+                if uri.scheme_str() != Some("http") || uri.authority().is_none() {
+                    return Err(format!(
+                        "Invalid upstream (expected http://host:port): {}",
+                        uri
+                    ));
+                }
+                let uri = Uri::builder()
+                    .scheme("http")
+                    .authority(uri.authority().unwrap().as_str())
+                    .path_and_query("/")
+                    .build()
+                    .map_err(|e| e.to_string())?;
+                Ok(Arc::new(Upstream::new(uri)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut http = HttpConnector::new();
+        http.set_connect_timeout(Some(CONNECT_TIMEOUT));
+        let client = Client::builder(TokioExecutor::new())
+            .pool_timer(TokioTimer::new())
+            .build(http);
+        Ok(Self {
+            upstreams,
+            balancing,
+            next: AtomicUsize::new(0),
+            client,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            health_check_path: DEFAULT_HEALTH_CHECK_PATH.to_string(),
+            health_check_interval: DEFAULT_HEALTH_CHECK_INTERVAL,
+        })
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    pub fn health_check_path<S: Into<String>>(mut self, path: S) -> Self {
+        self.health_check_path = path.into();
+        self
+    }
+
+    pub fn health_check_interval(mut self, interval: Duration) -> Self {
+        self.health_check_interval = interval;
+        self
+    }
+
+    /// Select a healthy upstream (None if all upstreams are down)
+    fn select(&self) -> Option<Arc<Upstream>> {
+        match self.balancing {
+            Balancing::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                let len = self.upstreams.len();
+                (0..len)
+                    .map(|i| &self.upstreams[(start + i) % len])
+                    .find(|u| u.is_healthy())
+                    .cloned()
+            }
+            // on equal counts, the first one (min_by_key returns the first minimum)
+            Balancing::LeastConnections => self
+                .upstreams
+                .iter()
+                .filter(|u| u.is_healthy())
+                .min_by_key(|u| u.active.load(Ordering::Relaxed))
+                .cloned(),
+        }
+    }
+
+    /// Forward a request to an upstream, stream the response back
+    pub async fn forward(&self, req: Request<Incoming>) -> HandlerResult {
+        let Some(upstream) = self.select() else {
+            eprintln!("[proxy] no healthy upstream");
+            return status(StatusCode::SERVICE_UNAVAILABLE);
+        };
+        let guard = ActiveGuard::new(upstream.clone());
+
+        let (mut parts, body) = req.into_parts();
+        // the client sets the Host header (upstream authority), keep the original one
+        // (http/2 requests have no Host header: the :authority pseudo header is in the uri)
+        let client_host = parts.headers.remove(HOST).or_else(|| {
+            let authority = parts.uri.authority()?;
+            HeaderValue::from_str(authority.as_str()).ok()
+        });
+        let path_and_query = parts
+            .uri
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/");
+        let authority = upstream
+            .uri
+            .authority()
+            .map(|a| a.as_str())
+            .unwrap_or_default();
+        parts.uri = Uri::builder()
+            .scheme("http")
+            .authority(authority)
+            .path_and_query(path_and_query)
+            .build()?;
+        // the connection to the upstream is http/1.1 (whatever the client connection)
+        let client_version = std::mem::replace(&mut parts.version, Version::HTTP_11);
+
+        remove_hop_by_hop_headers(&mut parts.headers);
+        if let Some(addr) = parts.extensions.get::<RemoteAddr>() {
+            append_forwarded_for(&mut parts.headers, addr.0.ip());
+        }
+        if let Some(host) = client_host {
+            parts.headers.insert(X_FORWARDED_HOST, host);
+        }
+
+        let req = Request::from_parts(parts, body.map_err(BoxError::from).boxed());
+        let response =
+            match tokio::time::timeout(self.request_timeout, self.client.request(req)).await {
+                Ok(Ok(response)) => response,
+                Ok(Err(e)) => {
+                    eprintln!("[proxy] upstream {} error: {}", upstream.uri, e);
+                    return status(StatusCode::BAD_GATEWAY);
+                }
+                Err(_) => {
+                    eprintln!(
+                        "[proxy] upstream {}: no response after {:?}",
+                        upstream.uri, self.request_timeout
+                    );
+                    return status(StatusCode::GATEWAY_TIMEOUT);
+                }
+            };
+
+        let (mut parts, body) = response.into_parts();
+        parts.version = client_version;
+        remove_hop_by_hop_headers(&mut parts.headers);
+        // the request is in flight until the end of the response body (or the client is gone)
+        let body = body
+            .map_frame(move |frame| {
+                let _guard = &guard;
+                frame
+            })
+            .map_err(BoxError::from)
+            .boxed();
+        Ok(Response::from_parts(parts, body))
+    }
+
+    /// Check the upstreams health periodically (never returns, should be spawned)
+    pub async fn health_checks(self: Arc<Self>) {
+        let client = match HttpClient::builder()
+            .connect_timeout(HEALTH_CHECK_TIMEOUT)
+            .request_timeout(HEALTH_CHECK_TIMEOUT)
+            .max_retries(0)
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                eprintln!("[proxy] unable to build the health check client: {}", e);
+                return;
+            }
+        };
+        let mut interval = tokio::time::interval(self.health_check_interval);
+        loop {
+            interval.tick().await;
+            let checks = self.upstreams.iter().map(|upstream| {
+                let url = format!(
+                    "{}{}",
+                    upstream.uri.to_string().trim_end_matches('/'),
+                    self.health_check_path
+                );
+                let client = &client;
+                async move {
+                    let healthy = match client.get(&url).await {
+                        Ok(response) => response.status().is_success(),
+                        Err(_) => false,
+                    };
+                    let was_healthy = upstream.healthy.swap(healthy, Ordering::Relaxed);
+                    match (was_healthy, healthy) {
+                        (true, false) => println!("[proxy] upstream {} is down", upstream.uri),
+                        (false, true) => println!("[proxy] upstream {} is up", upstream.uri),
+                        _ => {}
+                    }
+                }
+            });
+            futures::future::join_all(checks).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(balancing: Balancing) -> Proxy {
+        let upstreams: Vec<Uri> = ["http://127.0.0.1:8001", "http://127.0.0.1:8002"]
+            .iter()
+            .map(|u| u.parse().unwrap())
+            .collect();
+        Proxy::new(&upstreams, balancing).unwrap()
+    }
+
+    fn port(upstream: Option<Arc<Upstream>>) -> Option<u16> {
+        upstream.and_then(|u| u.uri.port_u16())
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let proxy = proxy(Balancing::RoundRobin);
+        assert_eq!(port(proxy.select()), Some(8001));
+        assert_eq!(port(proxy.select()), Some(8002));
+        assert_eq!(port(proxy.select()), Some(8001));
+
+        proxy.upstreams[0].healthy.store(false, Ordering::Relaxed);
+        assert_eq!(port(proxy.select()), Some(8002));
+        assert_eq!(port(proxy.select()), Some(8002));
+        proxy.upstreams[1].healthy.store(false, Ordering::Relaxed);
+        assert_eq!(port(proxy.select()), None);
+    }
+
+    #[tokio::test]
+    async fn test_least_connections() {
+        let proxy = proxy(Balancing::LeastConnections);
+        let guard = ActiveGuard::new(proxy.select().unwrap());
+        assert_eq!(guard.0.uri.port_u16(), Some(8001));
+        assert_eq!(port(proxy.select()), Some(8002));
+        drop(guard);
+        assert_eq!(port(proxy.select()), Some(8001));
+    }
+
+    #[test]
+    fn test_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONNECTION, HeaderValue::from_static("keep-alive, x-custom"));
+        headers.insert("keep-alive", HeaderValue::from_static("timeout=5"));
+        headers.insert("x-custom", HeaderValue::from_static("1"));
+        headers.insert("transfer-encoding", HeaderValue::from_static("chunked"));
+        headers.insert("content-type", HeaderValue::from_static("text/plain"));
+        remove_hop_by_hop_headers(&mut headers);
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key("content-type"));
+
+        append_forwarded_for(&mut headers, "10.0.0.1".parse().unwrap());
+        append_forwarded_for(&mut headers, "127.0.0.1".parse().unwrap());
+        assert_eq!(headers[X_FORWARDED_FOR], "10.0.0.1, 127.0.0.1");
+    }
+
+    #[test]
+    fn test_forwarded_for_lines() {
+        // a list split in two header lines: no hop is lost
+        let mut headers = HeaderMap::new();
+        headers.append(
+            X_FORWARDED_FOR,
+            HeaderValue::from_static("10.0.0.1, 10.0.0.2"),
+        );
+        headers.append(X_FORWARDED_FOR, HeaderValue::from_static("10.0.0.3"));
+        append_forwarded_for(&mut headers, "127.0.0.1".parse().unwrap());
+        assert_eq!(headers.get_all(X_FORWARDED_FOR).iter().count(), 1);
+        assert_eq!(
+            headers[X_FORWARDED_FOR],
+            "10.0.0.1, 10.0.0.2, 10.0.0.3, 127.0.0.1"
+        );
+    }
+}
@@ -0,0 +1,511 @@
+//! A small router: handlers are registered per method & path pattern
+//!
+//! Path patterns:
+//! * /echo: literal segment
+//! * /echo/:transform: named parameter (a single segment)
+//! * /static/*path: named wildcard (the remaining segments, must be the last one)
+//!
+//! Routes are matched in registration order (first match wins). If the path matches but
+//! the method does not, a 405 (Method Not Allowed) is returned with an Allow header.
+//!
+//! Middlewares wrap the handlers (global or per route), e.g. for logging, auth or timing:
+//! a middleware receives the request and a `Next`, that it can call (or not) to run the rest
+//! of the chain.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+use hyper::body::Incoming;
+use hyper::header::{HeaderValue, ALLOW, AUTHORIZATION, CONTENT_LENGTH, WWW_AUTHENTICATE};
+use hyper::{Method, Request, Response, StatusCode};
+
+use crate::body::{empty, full, BoxError, BoxedBody};
+
+pub type HandlerResult = Result<Response<BoxedBody>, BoxError>;
+pub type HandlerFuture = Pin<Box<dyn Future<Output = HandlerResult> + Send>>;
+
+/// Parameters extracted from the path (e.g. transform for /echo/:transform)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+}
+
+pub trait Handler: Send + Sync + 'static {
+    fn call(&self, req: Request<Incoming>, params: Params) -> HandlerFuture;
+}
+
+impl<F, Fut> Handler for F
+where
+    F: Fn(Request<Incoming>, Params) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = HandlerResult> + Send + 'static,
+{
+    fn call(&self, req: Request<Incoming>, params: Params) -> HandlerFuture {
+        Box::pin(self(req, params))
+    }
+}
+
+pub trait Middleware: Send + Sync + 'static {
+    fn call(&self, req: Request<Incoming>, params: Params, next: Next) -> HandlerFuture;
+}
+
+impl<F, Fut> Middleware for F
+where
+    F: Fn(Request<Incoming>, Params, Next) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = HandlerResult> + Send + 'static,
+{
+    fn call(&self, req: Request<Incoming>, params: Params, next: Next) -> HandlerFuture {
+        Box::pin(self(req, params, next))
+    }
+}
+
+/// The rest of a middleware chain (the remaining middlewares then the handler)
+pub struct Next {
+    middlewares: Arc<[Arc<dyn Middleware>]>,
+    index: usize,
+    handler: Arc<dyn Handler>,
+}
+
+impl Next {
+    fn new(middlewares: Vec<Arc<dyn Middleware>>, handler: Arc<dyn Handler>) -> Self {
+        Self {
+            middlewares: middlewares.into(),
+            index: 0,
+            handler,
+        }
+    }
+
+    pub fn run(self, req: Request<Incoming>, params: Params) -> HandlerFuture {
+        match self.middlewares.get(self.index).cloned() {
+            Some(middleware) => {
+                let next = Next {
+                    index: self.index + 1,
+                    ..self
+                };
+                middleware.call(req, params, next)
+            }
+            None => self.handler.call(req, params),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Pattern(Vec<Segment>);
+
+impl Pattern {
+    fn parse(pattern: &str) -> Self {
+        let segments: Vec<Segment> = pattern
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                if let Some(name) = s.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = s.strip_prefix('*') {
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Literal(s.to_string())
+                }
+            })
+            .collect();
+        if let Some(i) = segments
+            .iter()
+            .position(|s| matches!(s, Segment::Wildcard(_)))
+        {
+            assert_eq!(
+                i,
+                segments.len() - 1,
+                "wildcard must be the last segment: {}",
+                pattern
+            );
+        }
+        Self(segments)
+    }
+
+    /// Match a request path, return the extracted parameters
+    fn matches(&self, path: &str) -> Option<Params> {
+        let mut parts = path.split('/').filter(|s| !s.is_empty());
+        let mut params = vec![];
+        for segment in self.0.iter() {
+            match segment {
+                Segment::Literal(lit) => {
+                    if parts.next()? != lit {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => params.push((name.clone(), parts.next()?.to_string())),
+                Segment::Wildcard(name) => {
+                    let rest: Vec<&str> = parts.by_ref().collect();
+                    params.push((name.clone(), rest.join("/")));
+                }
+            }
+        }
+        match parts.next() {
+            Some(_) => None,
+            None => Some(Params(params)),
+        }
+    }
+}
+
+/// The pattern of the matched route (e.g. /echo/:transform), inserted in the request
+/// extensions by the router (not set for 404 & 405 responses)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchedPath(pub String);
+
+pub struct Route {
+    method: Method,
+    path: String,
+    pattern: Pattern,
+    handler: Arc<dyn Handler>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl Route {
+    /// Add a middleware to this route only (run after the global middlewares)
+    pub fn layer<M: Middleware>(&mut self, middleware: M) -> &mut Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a middleware to every route (including 404 & 405 responses)
+    pub fn middleware<M: Middleware>(&mut self, middleware: M) -> &mut Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    pub fn route<H: Handler>(&mut self, method: Method, pattern: &str, handler: H) -> &mut Route {
+        self.routes.push(Route {
+            method,
+            path: pattern.to_string(),
+            pattern: Pattern::parse(pattern),
+            handler: Arc::new(handler),
+            middlewares: vec![],
+        });
+        self.routes.last_mut().unwrap()
+    }
+
+    pub fn get<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Route {
+        self.route(Method::GET, pattern, handler)
+    }
+
+    pub fn post<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Route {
+        self.route(Method::POST, pattern, handler)
+    }
+
+    pub fn put<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Route {
+        self.route(Method::PUT, pattern, handler)
+    }
+
+    pub fn delete<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Route {
+        self.route(Method::DELETE, pattern, handler)
+    }
+
+    /// Find the route for a request: Ok(route, params) or Err(allowed methods) if only the path matches
+    fn find(&self, method: &Method, path: &str) -> Result<(&Route, Params), Vec<Method>> {
+        let mut allowed = vec![];
+        let mut get_route = None;
+        for route in self.routes.iter() {
+            if let Some(params) = route.pattern.matches(path) {
+                if route.method == method {
+                    return Ok((route, params));
+                }
+                if route.method == Method::GET && get_route.is_none() {
+                    get_route = Some((route, params));
+                }
+                if !allowed.contains(&route.method) {
+                    allowed.push(route.method.clone());
+                }
+            }
+        }
+        // without a HEAD route, HEAD is answered by the GET route (hyper does not send the body)
+        if let (&Method::HEAD, Some(get_route)) = (method, get_route) {
+            return Ok(get_route);
+        }
+        if allowed.contains(&Method::GET) {
+            allowed.push(Method::HEAD);
+        }
+        Err(allowed)
+    }
+
+    /// Run the route handler (and the middlewares) for a request
+    ///
+    /// Note: a handler error is turned into a 500 response (the error is logged), so this can be
+    ///       used as a hyper service without error: service_fn(move |req| router.dispatch(req))
+    pub fn dispatch(
+        &self,
+        mut req: Request<Incoming>,
+    ) -> impl Future<Output = Result<Response<BoxedBody>, Infallible>> + Send + 'static {
+        let (handler, route_middlewares, params): (Arc<dyn Handler>, &[_], _) =
+            match self.find(req.method(), req.uri().path()) {
+                Ok((route, params)) => {
+                    req.extensions_mut().insert(MatchedPath(route.path.clone()));
+                    (route.handler.clone(), &route.middlewares, params)
+                }
+                Err(allowed) if allowed.is_empty() => (
+                    Arc::new(status_handler(StatusCode::NOT_FOUND)),
+                    &[],
+                    Params::default(),
+                ),
+                Err(allowed) => {
+                    let allow = allowed
+                        .iter()
+                        .map(|m| m.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", ");
+                    (Arc::new(method_not_allowed(allow)), &[], Params::default())
+                }
+            };
+
+        let middlewares = self
+            .middlewares
+            .iter()
+            .chain(route_middlewares.iter())
+            .cloned()
+            .collect();
+        let response = Next::new(middlewares, handler).run(req, params);
+        async move {
+            match response.await {
+                Ok(response) => Ok(response),
+                Err(e) => {
+                    eprintln!("[http] handler error: {}", e);
+                    let mut response = Response::new(empty());
+                    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    Ok(response)
+                }
+            }
+        }
+    }
+}
+
+fn status_handler(status: StatusCode) -> impl Handler {
+    move |_req, _params| async move {
+        let mut response = Response::new(empty());
+        *response.status_mut() = status;
+        Ok(response)
+    }
+}
+
+fn method_not_allowed(allow: String) -> impl Handler {
+    move |_req, _params| {
+        let allow = allow.clone();
+        async move {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            if let Ok(allow) = HeaderValue::from_str(&allow) {
+                response.headers_mut().insert(ALLOW, allow);
+            }
+            Ok(response)
+        }
+    }
+}
+
+// Middlewares
+
+/// Add a X-Response-Time header (time to produce the response headers, in ms)
+pub struct Timing;
+
+impl Middleware for Timing {
+    fn call(&self, req: Request<Incoming>, params: Params, next: Next) -> HandlerFuture {
+        Box::pin(async move {
+            let start = Instant::now();
+            let mut response = next.run(req, params).await?;
+            let elapsed = format!("{:.3}ms", start.elapsed().as_secs_f64() * 1000.0);
+            if let Ok(value) = HeaderValue::from_str(&elapsed) {
+                response.headers_mut().insert("x-response-time", value);
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// Refuse requests with a Content-Length larger than `max_size` (413 Payload Too Large)
+///
+/// Note: bodies without a Content-Length (chunked) must be limited by the handlers
+///       (see body::limited / body::buffer_body)
+pub struct MaxBodySize(pub u64);
+
+impl Middleware for MaxBodySize {
+    fn call(&self, req: Request<Incoming>, params: Params, next: Next) -> HandlerFuture {
+        let content_length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        match content_length {
+            Some(len) if len > self.0 => {
+                let max_size = self.0;
+                Box::pin(async move { Ok(payload_too_large(max_size)) })
+            }
+            _ => next.run(req, params),
+        }
+    }
+}
+
+pub fn payload_too_large(max_size: u64) -> Response<BoxedBody> {
+    let mut response = Response::new(full(format!(
+        "Payload too large (max: {} bytes)\n",
+        max_size
+    )));
+    *response.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+    response
+}
+
+/// Require an `Authorization: Bearer <token>` header, 401 otherwise
+pub struct BearerAuth {
+    token: String,
+}
+
+impl BearerAuth {
+    pub fn new<S: Into<String>>(token: S) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+
+    fn is_authorized(&self, value: Option<&HeaderValue>) -> bool {
+        value
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(token.trim().as_bytes(), self.token.as_bytes()))
+    }
+}
+
+/// Compare two tokens in a time which only depends on their lengths (not on the position of the
+/// first difference)
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl Middleware for BearerAuth {
+    fn call(&self, req: Request<Incoming>, params: Params, next: Next) -> HandlerFuture {
+        if self.is_authorized(req.headers().get(AUTHORIZATION)) {
+            return next.run(req, params);
+        }
+        Box::pin(async move {
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::UNAUTHORIZED;
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(p: &[(&str, &str)]) -> Params {
+        Params(
+            p.iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        let pattern = Pattern::parse("/echo/:transform");
+        assert_eq!(
+            pattern.matches("/echo/uppercase"),
+            Some(params(&[("transform", "uppercase")]))
+        );
+        assert_eq!(pattern.matches("/echo"), None);
+        assert_eq!(pattern.matches("/echo/uppercase/more"), None);
+        assert_eq!(pattern.matches("/other/uppercase"), None);
+
+        let pattern = Pattern::parse("/");
+        assert_eq!(pattern.matches("/"), Some(Params::default()));
+        assert_eq!(pattern.matches("/echo"), None);
+
+        let pattern = Pattern::parse("/static/*path");
+        assert_eq!(
+            pattern.matches("/static/css/main.css"),
+            Some(params(&[("path", "css/main.css")]))
+        );
+        assert_eq!(pattern.matches("/static"), Some(params(&[("path", "")])));
+    }
+
+    #[test]
+    fn test_router_find() {
+        let ok = |_req, _params| async { Ok(Response::new(empty())) };
+        let mut router = Router::new();
+        router.get("/", ok);
+        router.post("/echo", ok);
+        router.post("/echo/:transform", ok);
+        router.put("/kv/:key", ok);
+        router.delete("/kv/:key", ok);
+
+        let (route, params) = router.find(&Method::POST, "/echo/reversed").unwrap();
+        assert_eq!(route.method, Method::POST);
+        assert_eq!(params.get("transform"), Some("reversed"));
+
+        assert_eq!(
+            router.find(&Method::GET, "/echo").err(),
+            Some(vec![Method::POST])
+        );
+        assert_eq!(
+            router.find(&Method::GET, "/kv/a").err(),
+            Some(vec![Method::PUT, Method::DELETE])
+        );
+        assert_eq!(router.find(&Method::GET, "/nope").err(), Some(vec![]));
+
+        // HEAD: the GET route (unless there is a HEAD route)
+        let (route, _) = router.find(&Method::HEAD, "/").unwrap();
+        assert_eq!(route.method, Method::GET);
+        assert_eq!(
+            router.find(&Method::POST, "/").err(),
+            Some(vec![Method::GET, Method::HEAD])
+        );
+        assert_eq!(
+            router.find(&Method::HEAD, "/echo").err(),
+            Some(vec![Method::POST])
+        );
+        router.route(Method::HEAD, "/", ok);
+        let (route, _) = router.find(&Method::HEAD, "/").unwrap();
+        assert_eq!(route.method, Method::HEAD);
+    }
+
+    #[test]
+    fn test_bearer_auth() {
+        let auth = BearerAuth::new("s3cret");
+        assert!(auth.is_authorized(Some(&HeaderValue::from_static("Bearer s3cret"))));
+        assert!(!auth.is_authorized(Some(&HeaderValue::from_static("Bearer other"))));
+        assert!(!auth.is_authorized(Some(&HeaderValue::from_static("Bearer s3cre"))));
+        assert!(!auth.is_authorized(Some(&HeaderValue::from_static("Bearer s3cret2"))));
+        assert!(!auth.is_authorized(Some(&HeaderValue::from_static("Basic s3cret"))));
+        assert!(!auth.is_authorized(None));
+    }
+}
@@ -0,0 +1,678 @@
+//! The echo server: routes (see make_router), connections (plain tcp / tls) & graceful shutdown
+//!
+//! `Server::bind` binds the listener (port 0: ephemeral port, see `local_addr`), `run` serves
+//! until a shutdown: an os signal (or any future given to `run`), an authenticated
+//! POST /stop or a `ShutdownHandle`.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::StreamExt;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::{GracefulShutdown, Watcher};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio_future_05::MyStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+
+use crate::access_log::{AccessLog, LogFormat, RemoteAddr, RequestId};
+use crate::body::{
+    buffer_body, collect_limited, empty, full, limited, BodyLimits, BoxError, BoxedBody,
+    BufferError, Buffered, SpillFile,
+};
+use crate::compression::{decoded_body, Compression};
+use crate::json_echo;
+use crate::kv::{self, KvStore};
+use crate::metrics::{metrics_handler, Metrics, RecordMetrics};
+use crate::proxy::Proxy;
+use crate::router::{
+    payload_too_large, BearerAuth, HandlerResult, MaxBodySize, Params, Router, Timing,
+};
+use crate::shutdown::{ConnectionTracker, ShutdownReason};
+use crate::sse::{last_event_id, Event, Sse};
+use crate::static_files::StaticFiles;
+use crate::upload::Uploads;
+use crate::websocket::ws_handler;
+
+const MAX_STOP_BODY_SIZE: u64 = 1024;
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+const REVERSE_CHUNK_SIZE: u64 = 64 * 1024;
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const EVENTS_COUNT: u32 = 100;
+const EVENTS_INTERVAL: Duration = Duration::from_secs(1);
+const EVENTS_RETRY: Duration = Duration::from_secs(3);
+
+async fn index(_req: Request<Incoming>, _params: Params) -> HandlerResult {
+    Ok(Response::new(full(
+        "Try POST'ing data to /echo, e.g.: curl http://127.0.0.1:8000/echo -X POST -d 'hello world'",
+    )))
+}
+
+/// Map a buffering error to a response (413 if the body is too large)
+fn buffer_error_response(e: BufferError) -> HandlerResult {
+    match e {
+        BufferError::TooLarge(max_size) => Ok(payload_too_large(max_size)),
+        BufferError::Body(e) => Err(e),
+        BufferError::Io(e) => {
+            eprintln!("[echo] io error: {}", e);
+            let mut response = Response::new(empty());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            Ok(response)
+        }
+    }
+}
+
+/// POST /stop (requires the admin token)
+async fn stop(
+    req: Request<Incoming>,
+    tx: Sender<bool>,
+    tracker: Arc<ConnectionTracker>,
+    drain_timeout: Duration,
+) -> HandlerResult {
+    println!("[echo] Got stop...");
+
+    let full_body: Bytes = match collect_limited(req.into_body(), MAX_STOP_BODY_SIZE).await {
+        Ok(body) => body,
+        Err(e) => return buffer_error_response(e),
+    };
+
+    // tx.send(false).await;
+    let to_send: bool = !full_body.is_empty();
+
+    if let Err(e) = tx.send(to_send).await {
+        eprintln!("Unable to send to channel: {}", e);
+    }
+
+    if !to_send {
+        return Ok(Response::new(full("Not stopping (empty body)\n")));
+    }
+
+    // Note: this connection is still open (and will be closed after this response)
+    let others = tracker.open().saturating_sub(1);
+    // Response::new("Thanks for stopping the server...".into()))
+    Ok(Response::new(full(format!(
+        "Thanks for that!! Will stop the server... {} other connection(s) still open, drain timeout: {:?}\n",
+        others, drain_timeout
+    ))))
+}
+
+async fn echo(req: Request<Incoming>, limits: Arc<BodyLimits>) -> HandlerResult {
+    // just echo back what was send (decompressed if sent with a Content-Encoding)
+    let body = match decoded_body(req) {
+        Ok(body) => body,
+        Err(e) => return Ok(e.into_response()),
+    };
+    Ok(Response::new(limited(body, limits.max_size)))
+}
+
+/// Reverse a (spilled) body: read the file from the end, chunk by chunk
+fn reversed_file_body(file: SpillFile) -> BoxedBody {
+    let remaining = file.len();
+    let chunks = futures::stream::try_unfold((file, remaining), |(file, remaining)| async move {
+        if remaining == 0 {
+            return Ok::<_, std::io::Error>(None);
+        }
+        let len = remaining.min(REVERSE_CHUNK_SIZE);
+        let offset = remaining - len;
+        let chunk = file.read_at(offset, len as usize).await?;
+        let reversed: Bytes = chunk.iter().rev().cloned().collect();
+        // Note: the file is removed when the stream is dropped (end of body or client gone)
+        Ok(Some((Frame::data(reversed), (file, offset))))
+    });
+    StreamBody::new(chunks)
+        .map_err(|e| -> BoxError { Box::new(e) })
+        .boxed()
+}
+
+/// POST /echo/:transform (uppercase or reversed)
+async fn echo_transform(
+    req: Request<Incoming>,
+    params: Params,
+    limits: Arc<BodyLimits>,
+) -> HandlerResult {
+    let mut response = Response::new(empty());
+    // the transforms apply to the decompressed body
+    let body = match decoded_body(req) {
+        Ok(body) => body,
+        Err(e) => return Ok(e.into_response()),
+    };
+
+    match params.get("transform") {
+        Some("uppercase") => {
+            // map each data frame (chunk) as it arrives, the body is never fully buffered
+            // Note: the request body is only read when the response body is polled (backpressure)
+            //       if the body is too large, the response is aborted (headers are already sent)
+            let mapping = limited(body, limits.max_size).map_frame(|frame| {
+                frame.map_data(|chunk| {
+                    chunk
+                        .iter()
+                        .map(|byte| byte.to_ascii_uppercase())
+                        .collect::<Bytes>()
+                })
+            });
+            *response.body_mut() = mapping.boxed();
+        }
+
+        Some("reversed") => {
+            // the whole body is required: buffered in memory or in a temp file if large
+            match buffer_body(body, &limits).await {
+                Ok(Buffered::Memory(full_body)) => {
+                    println!("full_body: {} bytes (memory)", full_body.len());
+                    // iter() -> iterator over the slice
+                    // rev() -> (aka std::iter::Rev): reversed iterator
+                    // cloned() -> (aka std::iter::Cloned) iterator that clone the underlying iterator
+                    let reversed: Vec<u8> = full_body.iter().rev().cloned().collect::<Vec<u8>>();
+                    *response.body_mut() = full(reversed);
+                }
+                Ok(Buffered::File(file)) => {
+                    println!(
+                        "full_body: {} bytes (temp file: {})",
+                        file.len(),
+                        file.path().display()
+                    );
+                    *response.body_mut() = reversed_file_body(file);
+                }
+                Err(e) => return buffer_error_response(e),
+            }
+        }
+
+        _ => {
+            *response.status_mut() = StatusCode::NOT_FOUND;
+        }
+    };
+
+    Ok(response)
+}
+
+/// GET /events: the values of MyStream (tokio_future_05) as events, the value is the event id
+async fn events(req: Request<Incoming>, _params: Params) -> HandlerResult {
+    // resume: skip the events already received
+    let skip = last_event_id(&req)
+        .and_then(|id| id.parse::<u32>().ok())
+        .unwrap_or(0);
+    let stream = MyStream::new(EVENTS_COUNT)
+        .skip(skip as usize)
+        .then(|value| async move {
+            tokio::time::sleep(EVENTS_INTERVAL).await;
+            Event::new(format!("tick {}", value))
+                .id(value.to_string())
+                .event("tick")
+        });
+    Ok(Sse::new(stream).retry(EVENTS_RETRY).into_response())
+}
+
+#[derive(Debug)]
+pub struct ServerConfig {
+    pub limits: BodyLimits,
+    /// Bearer token required by /stop (disabled if None)
+    pub admin_token: Option<String>,
+    /// Max time to wait for the connections to close on shutdown
+    pub drain_timeout: Duration,
+    /// Files served under /static/
+    pub static_files: Option<StaticFiles>,
+    /// multipart/form-data uploads on /upload (disabled if None)
+    pub uploads: Option<Uploads>,
+    /// Compress responses larger than this size (None: compression disabled)
+    pub compression_min_size: Option<u64>,
+    pub log_format: LogFormat,
+    /// Proxy mode: forward the requests to upstream servers
+    pub proxy: Option<Arc<Proxy>>,
+    /// Http2 with prior knowledge on the plain tcp listener (h2c)
+    pub h2c: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            limits: BodyLimits::default(),
+            admin_token: None,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            static_files: None,
+            uploads: None,
+            compression_min_size: Some(Compression::default().min_size),
+            log_format: LogFormat::Combined,
+            proxy: None,
+            h2c: false,
+        }
+    }
+}
+
+/// GET|HEAD /static/*path
+async fn serve_static(
+    req: Request<Incoming>,
+    params: Params,
+    files: Arc<StaticFiles>,
+) -> HandlerResult {
+    let (parts, _body) = req.into_parts();
+    files
+        .serve(&parts, params.get("path").unwrap_or_default())
+        .await
+}
+
+fn make_router(tx: Sender<bool>, config: &ServerConfig, tracker: Arc<ConnectionTracker>) -> Router {
+    let limits = Arc::new(config.limits.clone());
+    let max_size = limits.max_size;
+    let metrics = Arc::new(Metrics::new());
+    let mut router = Router::new();
+    // RequestId first: the id is logged by AccessLog
+    router
+        .middleware(RequestId)
+        .middleware(AccessLog {
+            format: config.log_format,
+        })
+        .middleware(RecordMetrics(metrics.clone()))
+        .middleware(Timing);
+    if let Some(min_size) = config.compression_min_size {
+        router.middleware(Compression { min_size });
+    }
+
+    if let Some(proxy) = config.proxy.as_ref() {
+        router.get("/metrics", move |_req, _params| {
+            metrics_handler(metrics.clone())
+        });
+        for method in [
+            Method::GET,
+            Method::HEAD,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::PATCH,
+            Method::OPTIONS,
+        ] {
+            let proxy = proxy.clone();
+            router
+                .route(method, "/*path", move |req, _params| {
+                    let proxy = proxy.clone();
+                    async move { proxy.forward(req).await }
+                })
+                .layer(MaxBodySize(max_size));
+        }
+        return router;
+    }
+
+    router.get("/", index);
+    router.get("/ws", ws_handler);
+    router.get("/events", events);
+    router.get("/metrics", move |_req, _params| {
+        metrics_handler(metrics.clone())
+    });
+    // MaxBodySize per route: /upload has its own limit (--max-upload-size, JSON errors)
+    let echo_limits = limits.clone();
+    router
+        .post("/echo", move |req, _params| echo(req, echo_limits.clone()))
+        .layer(MaxBodySize(max_size));
+    // before /echo/:transform (first match wins)
+    router
+        .post("/echo/json", move |req, _params| {
+            json_echo::handle(req, max_size)
+        })
+        .layer(MaxBodySize(max_size));
+    router
+        .post("/echo/:transform", move |req, params| {
+            echo_transform(req, params, limits.clone())
+        })
+        .layer(MaxBodySize(max_size));
+
+    let store = Arc::new(RwLock::new(KvStore::new()));
+    let kv_store = store.clone();
+    router.get("/kv", move |req, _params| kv::list(req, kv_store.clone()));
+    for method in [Method::GET, Method::HEAD] {
+        let store = store.clone();
+        router.route(method, "/kv/:key", move |req, params| {
+            kv::get(req, params, store.clone())
+        });
+    }
+    let kv_store = store.clone();
+    router
+        .put("/kv/:key", move |req, params| {
+            kv::put(req, params, kv_store.clone(), max_size)
+        })
+        .layer(MaxBodySize(max_size));
+    router.delete("/kv/:key", move |_req, params| {
+        kv::delete(params, store.clone())
+    });
+
+    if let Some(files) = config.static_files.as_ref() {
+        let files = Arc::new(files.clone());
+        for method in [Method::GET, Method::HEAD] {
+            let files = files.clone();
+            router.route(method, "/static/*path", move |req, params| {
+                serve_static(req, params, files.clone())
+            });
+        }
+    }
+    if let Some(uploads) = config.uploads.as_ref() {
+        let uploads = Arc::new(uploads.clone());
+        router.post("/upload", move |req, _params| {
+            let uploads = uploads.clone();
+            async move { uploads.handle(req).await }
+        });
+    }
+    if let Some(token) = config.admin_token.as_ref() {
+        // handlers can be called multiple times (once per request) so we clone tx
+        // and move the clone into the async block
+        let drain_timeout = config.drain_timeout;
+        router
+            .post("/stop", move |req, _params| {
+                stop(req, tx.clone(), tracker.clone(), drain_timeout)
+            })
+            .layer(BearerAuth::new(token.as_str()));
+    }
+    router
+}
+
+async fn wait_for_stop_true(mut rx: Receiver<bool>) {
+    loop {
+        let stop = rx.recv().await;
+        println!("Got a stop value: {:?}", stop);
+        match stop {
+            Some(true) => break,
+            Some(false) => continue,
+            // all senders dropped (no more /stop handler nor ShutdownHandle)
+            None => std::future::pending::<()>().await,
+        }
+    }
+}
+
+/// Resolve on a stop request (POST /stop or the ShutdownHandle): Server::run then stops
+/// accepting, asks the connections to close (cancellation token) and drains them
+async fn shutdown_from_channel(rx: Receiver<bool>) {
+    wait_for_stop_true(rx).await;
+}
+
+/// Http protocol(s) served on a connection
+#[derive(Debug, Clone, Copy)]
+enum HttpProtocol {
+    Http1,
+    Http2,
+    /// Http1 or http2 with prior knowledge (detected with the http2 connection preface)
+    Auto,
+}
+
+/// Serve all the requests of a connection (plain tcp or tls stream)
+///
+/// On shutdown (`closing`), the connection is closed after its in flight requests. The watcher is
+/// held until the connection is closed: the graceful shutdown waits for it
+async fn serve_connection<I>(
+    io: I,
+    peer_addr: SocketAddr,
+    protocol: HttpProtocol,
+    router: Arc<Router>,
+    watcher: Watcher,
+    closing: CancellationToken,
+) where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let service = service_fn(move |mut req: Request<Incoming>| {
+        // the client address, for the access log
+        req.extensions_mut().insert(RemoteAddr(peer_addr));
+        router.dispatch(req)
+    });
+    let result = match protocol {
+        // Note: the auto builder ignores http1_only when serving with upgrades (http2 with prior
+        //       knowledge would be served): http1 connections are served by the http1 builder
+        //       with_upgrades: http1 connections can be upgraded (e.g. WebSocket, see /ws)
+        HttpProtocol::Http1 => {
+            let _watcher = watcher;
+            let conn = http1::Builder::new()
+                .serve_connection(io, service)
+                .with_upgrades();
+            let mut conn = std::pin::pin!(conn);
+            // the graceful watcher does not support upgradeable http1 connections
+            tokio::select! {
+                result = conn.as_mut() => result,
+                _ = closing.cancelled() => {
+                    conn.as_mut().graceful_shutdown();
+                    conn.await
+                }
+            }
+            .map_err(BoxError::from)
+        }
+        // http2 streams (requests) are served in their own task, spawned by the executor
+        // Note: into_owned so the connection does not borrow the builder
+        HttpProtocol::Http2 => {
+            let conn = auto::Builder::new(TokioExecutor::new())
+                .http2_only()
+                .serve_connection(io, service)
+                .into_owned();
+            watcher.watch(conn).await
+        }
+        HttpProtocol::Auto => {
+            let conn = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(io, service)
+                .into_owned();
+            watcher.watch(conn).await
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Server error: {}", e);
+    }
+}
+
+/// Tls handshake then serve the connection with the protocol negotiated with ALPN
+async fn serve_tls_connection(
+    stream: TcpStream,
+    peer_addr: SocketAddr,
+    acceptor: TlsAcceptor,
+    router: Arc<Router>,
+    watcher: Watcher,
+    closing: CancellationToken,
+) {
+    let tls_stream =
+        match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+            Ok(Ok(tls_stream)) => tls_stream,
+            Ok(Err(e)) => {
+                eprintln!("Tls handshake error: {}", e);
+                return;
+            }
+            Err(_) => {
+                eprintln!("Tls handshake timeout");
+                return;
+            }
+        };
+    let protocol = match tls_stream.get_ref().1.alpn_protocol() {
+        Some(b"h2") => HttpProtocol::Http2,
+        // http/1.1 or no ALPN
+        _ => HttpProtocol::Http1,
+    };
+    serve_connection(
+        TokioIo::new(tls_stream),
+        peer_addr,
+        protocol,
+        router,
+        watcher,
+        closing,
+    )
+    .await
+}
+
+/// Accept a connection on the tls listener (never completes if there is no tls listener)
+async fn accept_tls(
+    listener: Option<&(TcpListener, TlsAcceptor)>,
+) -> std::io::Result<(TcpStream, SocketAddr, TlsAcceptor)> {
+    match listener {
+        Some((listener, acceptor)) => {
+            let (stream, peer_addr) = listener.accept().await?;
+            Ok((stream, peer_addr, acceptor.clone()))
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Stop a running server, like an authenticated POST /stop (with a non empty body)
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle(Sender<bool>);
+
+impl ShutdownHandle {
+    pub async fn shutdown(&self) {
+        // Err: the server is already stopped
+        let _ = self.0.send(true).await;
+    }
+}
+
+/// A bound server (listening but not accepting connections until `run`)
+pub struct Server {
+    listener: TcpListener,
+    tls_listener: Option<(TcpListener, TlsAcceptor)>,
+    protocol: HttpProtocol,
+    router: Arc<Router>,
+    drain_timeout: Duration,
+    tracker: Arc<ConnectionTracker>,
+    tx: Sender<bool>,
+    rx: Receiver<bool>,
+}
+
+impl Server {
+    /// Bind the plain tcp listener (e.g. 127.0.0.1:0 for an ephemeral port)
+    pub async fn bind(addr: SocketAddr, config: ServerConfig) -> std::io::Result<Self> {
+        // hyper 1.x has no Server anymore: we accept tcp connections ourselves and
+        // serve each connection (http1 or http2) in its own task
+        let listener = TcpListener::bind(addr).await?;
+        let tracker = Arc::new(ConnectionTracker::default());
+        let (tx, rx) = mpsc::channel(1);
+        let router = Arc::new(make_router(tx.clone(), &config, tracker.clone()));
+        Ok(Self {
+            listener,
+            tls_listener: None,
+            protocol: match config.h2c {
+                true => HttpProtocol::Auto,
+                false => HttpProtocol::Http1,
+            },
+            router,
+            drain_timeout: config.drain_timeout,
+            tracker,
+            tx,
+            rx,
+        })
+    }
+
+    /// Also accept https connections (http2 or http1.1 negotiated with ALPN)
+    pub fn tls(mut self, listener: TcpListener, acceptor: TlsAcceptor) -> Self {
+        self.tls_listener = Some((listener, acceptor));
+        self
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.tx.clone())
+    }
+
+    /// Serve until `signal` completes or a stop is requested (POST /stop, ShutdownHandle),
+    /// then wait for the open connections to close (at most the drain timeout)
+    pub async fn run<F>(self, signal: F) -> ShutdownReason
+    where
+        F: Future<Output = ShutdownReason>,
+    {
+        let Self {
+            listener,
+            tls_listener,
+            protocol,
+            router,
+            drain_timeout,
+            tracker,
+            tx,
+            rx,
+        } = self;
+        // only the /stop handler & the shutdown handles can send
+        drop(tx);
+        // Keep track of the connections in order to wait for them on shutdown
+        let graceful = GracefulShutdown::new();
+        let closing = CancellationToken::new();
+        // Wait for the signal or an (authenticated) POST to /stop
+        let mut shutdown = std::pin::pin!(async {
+            tokio::select! {
+                reason = signal => reason,
+                _ = shutdown_from_channel(rx) => ShutdownReason::Admin,
+            }
+        });
+
+        if let Ok(addr) = listener.local_addr() {
+            println!("Listening on http://{} ({:?})", addr, protocol);
+        }
+
+        let reason = loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (stream, peer_addr) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            eprintln!("Accept error: {}", e);
+                            continue;
+                        }
+                    };
+                    // Adapt tokio io traits (AsyncRead / AsyncWrite) to hyper io traits
+                    let io = TokioIo::new(stream);
+                    let router = router.clone();
+                    let watcher = graceful.watcher();
+                    let closing = closing.clone();
+                    let guard = tracker.track();
+                    tokio::spawn(async move {
+                        // the connection is counted until this task ends
+                        let _guard = guard;
+                        serve_connection(io, peer_addr, protocol, router, watcher, closing).await
+                    });
+                },
+
+                accepted = accept_tls(tls_listener.as_ref()) => {
+                    let (stream, peer_addr, acceptor) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            eprintln!("Accept error (tls): {}", e);
+                            continue;
+                        }
+                    };
+                    // the tls handshake is done in the connection task (not to block the accept loop)
+                    let router = router.clone();
+                    let watcher = graceful.watcher();
+                    let closing = closing.clone();
+                    let guard = tracker.track();
+                    tokio::spawn(async move {
+                        let _guard = guard;
+                        serve_tls_connection(stream, peer_addr, acceptor, router, watcher, closing)
+                            .await
+                    });
+                },
+
+                reason = &mut shutdown => {
+                    // stop accepting new connections
+                    drop(listener);
+                    drop(tls_listener);
+                    println!(
+                        "Shutting down ({:?}), waiting for {} connection(s) to close (deadline: {:?})...",
+                        reason,
+                        tracker.open(),
+                        drain_timeout
+                    );
+                    break reason;
+                }
+            }
+        };
+
+        // Connections are asked to close (after their current request) and we wait for them,
+        // in flight requests have until the deadline to complete
+        closing.cancel();
+        match tokio::time::timeout(drain_timeout, graceful.shutdown()).await {
+            Ok(()) => println!("All connections closed"),
+            Err(_) => println!(
+                "Drain deadline reached, closing {} connection(s) still open",
+                tracker.open()
+            ),
+        }
+        println!("Server stopped");
+        reason
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Why the server is shutting down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownReason {
+    CtrlC,
+    Sigterm,
+    /// Authenticated POST /stop
+    Admin,
+}
+
+/// Wait for Ctrl-C or (on unix) SIGTERM
+pub async fn os_signal() -> ShutdownReason {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm =
+            signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+        tokio::select! {
+            r = tokio::signal::ctrl_c() => {
+                r.expect("failed to install CTRL+C signal handler");
+                ShutdownReason::CtrlC
+            }
+            _ = sigterm.recv() => ShutdownReason::Sigterm,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install CTRL+C signal handler");
+        ShutdownReason::CtrlC
+    }
+}
+
+/// Count the open connections
+#[derive(Debug, Default)]
+pub struct ConnectionTracker {
+    open: AtomicUsize,
+}
+
+impl ConnectionTracker {
+    /// Track a new connection, until the returned guard is dropped
+    pub fn track(self: &Arc<Self>) -> ConnectionGuard {
+        self.open.fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard(self.clone())
+    }
+
+    pub fn open(&self) -> usize {
+        self.open.load(Ordering::SeqCst)
+    }
+}
+
+pub struct ConnectionGuard(Arc<ConnectionTracker>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.open.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_tracker() {
+        let tracker = Arc::new(ConnectionTracker::default());
+        let guard1 = tracker.track();
+        let guard2 = tracker.track();
+        assert_eq!(tracker.open(), 2);
+        drop(guard1);
+        assert_eq!(tracker.open(), 1);
+        drop(guard2);
+        assert_eq!(tracker.open(), 0);
+    }
+}
@@ -0,0 +1,202 @@
+//! Server-Sent Events: stream any `Stream` of events as a text/event-stream response
+//!
+//! * event ids (sent back by the browser in a Last-Event-ID header when it reconnects)
+//! * retry hint: reconnection delay, sent first
+//! * heartbeats: a comment line when no event has been sent for a while, so idle connections
+//!   (and proxies) do not time out, and a client gone is detected by the next write
+//!
+//! The event stream is owned by the response body: when the client disconnects, hyper drops
+//! the body, and the stream with it.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::Stream;
+use hyper::body::{Body, Frame};
+use hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Request, Response};
+use tokio::time::{Instant, Sleep};
+
+use crate::body::{BoxError, BoxedBody};
+
+pub const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: String,
+}
+
+/// Remove the characters not allowed in a field value (a newline ends the field)
+fn field_value(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !matches!(c, '\r' | '\n' | '\0'))
+        .collect()
+}
+
+impl Event {
+    pub fn new<S: Into<String>>(data: S) -> Self {
+        Self {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn id<S: AsRef<str>>(mut self, id: S) -> Self {
+        self.id = Some(field_value(id.as_ref()));
+        self
+    }
+
+    /// Event type (default: message)
+    pub fn event<S: AsRef<str>>(mut self, event: S) -> Self {
+        self.event = Some(field_value(event.as_ref()));
+        self
+    }
+
+    /// Serialize the event: one "data:" line per data line, an empty line ends the event
+    pub fn encode(&self) -> Bytes {
+        let mut out = String::new();
+        if let Some(id) = self.id.as_ref() {
+            out.push_str(&format!("id: {}\n", id));
+        }
+        if let Some(event) = self.event.as_ref() {
+            out.push_str(&format!("event: {}\n", event));
+        }
+        for line in self.data.lines() {
+            out.push_str(&format!("data: {}\n", line));
+        }
+        if self.data.is_empty() {
+            out.push_str("data:\n");
+        }
+        out.push('\n');
+        Bytes::from(out)
+    }
+}
+
+/// The Last-Event-ID sent by a reconnecting client
+pub fn last_event_id<B>(req: &Request<B>) -> Option<&str> {
+    req.headers()
+        .get(LAST_EVENT_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+}
+
+/// A text/event-stream response body
+pub struct Sse {
+    events: Pin<Box<dyn Stream<Item = Event> + Send + Sync>>,
+    retry: Option<Duration>,
+    keep_alive: Duration,
+    heartbeat: Pin<Box<Sleep>>,
+}
+
+impl Sse {
+    pub fn new<S>(events: S) -> Self
+    where
+        S: Stream<Item = Event> + Send + Sync + 'static,
+    {
+        Self {
+            events: Box::pin(events),
+            retry: None,
+            keep_alive: DEFAULT_KEEP_ALIVE,
+            heartbeat: Box::pin(tokio::time::sleep(DEFAULT_KEEP_ALIVE)),
+        }
+    }
+
+    /// Reconnection delay hint (sent before the first event)
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Send a heartbeat (comment) if no event has been sent for `keep_alive`
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self.heartbeat = Box::pin(tokio::time::sleep(keep_alive));
+        self
+    }
+
+    pub fn into_response(self) -> Response<BoxedBody> {
+        let mut response = Response::new(BoxedBody::new(self));
+        let headers = response.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        response
+    }
+
+    fn reset_heartbeat(&mut self) {
+        let deadline = Instant::now() + self.keep_alive;
+        self.heartbeat.as_mut().reset(deadline);
+    }
+}
+
+impl Body for Sse {
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Some(retry) = self.retry.take() {
+            let retry = format!("retry: {}\n\n", retry.as_millis());
+            return Poll::Ready(Some(Ok(Frame::data(Bytes::from(retry)))));
+        }
+        match self.events.as_mut().poll_next(cx) {
+            Poll::Ready(Some(event)) => {
+                self.reset_heartbeat();
+                return Poll::Ready(Some(Ok(Frame::data(event.encode()))));
+            }
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => {}
+        }
+        match self.heartbeat.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                self.reset_heartbeat();
+                Poll::Ready(Some(Ok(Frame::data(Bytes::from_static(
+                    b": keep-alive\n\n",
+                )))))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use http_body_util::BodyExt;
+
+    #[test]
+    fn test_event_encode() {
+        assert_eq!(Event::new("hello").encode(), "data: hello\n\n");
+        assert_eq!(
+            Event::new("line 1\nline 2").id("42").event("tick").encode(),
+            "id: 42\nevent: tick\ndata: line 1\ndata: line 2\n\n"
+        );
+        assert_eq!(Event::new("").id("a\nb").encode(), "id: ab\ndata:\n\n");
+    }
+
+    async fn next_data(body: &mut Sse) -> Bytes {
+        let frame = body.frame().await.unwrap().unwrap();
+        frame.into_data().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sse_body() {
+        let events =
+            futures::stream::iter(vec![Event::new("1").id("1")]).chain(futures::stream::pending());
+        let mut body = Sse::new(events)
+            .retry(Duration::from_secs(3))
+            .keep_alive(Duration::from_millis(10));
+        assert_eq!(next_data(&mut body).await, "retry: 3000\n\n");
+        assert_eq!(next_data(&mut body).await, "id: 1\ndata: 1\n\n");
+        // no more events: heartbeat
+        assert_eq!(next_data(&mut body).await, ": keep-alive\n\n");
+    }
+}
@@ -0,0 +1,420 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::TryStreamExt;
+use http_body_util::StreamBody;
+use hyper::body::Frame;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::http::request::Parts;
+use hyper::{Method, Response, StatusCode};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::body::{empty, full, BoxError, BoxedBody};
+use crate::router::HandlerResult;
+
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+/// Characters encoded in a path segment (listing links): all but unreserved characters
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Serve the files of a directory
+///
+/// * Content-Type guessed from the file extension
+/// * Range requests (a single range, 206 Partial Content / 416 Range Not Satisfiable)
+/// * conditional requests: ETag / If-None-Match, Last-Modified / If-Modified-Since (304)
+/// * optional directory listing (otherwise index.html or 404)
+///
+/// File content is streamed (read by chunks), never fully loaded in memory
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    listing: bool,
+    chunk_size: usize,
+}
+
+/// A byte range (inclusive end, as in the Range header)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Result of parsing a Range header against a file size
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No (usable) Range header: serve the whole file
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable,
+}
+
+impl StaticFiles {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            listing: false,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Enable directory listing (html)
+    pub fn listing(mut self, listing: bool) -> Self {
+        self.listing = listing;
+        self
+    }
+
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Map a (percent encoded) request path to a path under root
+    ///
+    /// Return None if the path tries to escape root (.., absolute path, ...)
+    pub fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let decoded = percent_decode_str(path).decode_utf8().ok()?;
+        let mut resolved = self.root.clone();
+        for segment in decoded.split('/') {
+            if segment.contains('\\') || segment.contains('\0') {
+                return None;
+            }
+            let mut components = Path::new(segment).components();
+            match (components.next(), components.next()) {
+                (None, _) | (Some(Component::CurDir), None) => continue,
+                (Some(Component::Normal(name)), None) => resolved.push(name),
+                // ParentDir, RootDir, Prefix (windows) or a segment with multiple components
+                _ => return None,
+            }
+        }
+        Some(resolved)
+    }
+
+    /// Serve a file (or list a directory), `path` is relative to root
+    pub async fn serve(&self, req: &Parts, path: &str) -> HandlerResult {
+        if req.method != Method::GET && req.method != Method::HEAD {
+            return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+        }
+        let Some(file_path) = self.resolve(path) else {
+            println!("[static] refused path: {}", path);
+            return Ok(status_response(StatusCode::FORBIDDEN));
+        };
+
+        // Symlinks could point outside of root
+        let (Ok(root), Ok(file_path)) = (
+            tokio::fs::canonicalize(&self.root).await,
+            tokio::fs::canonicalize(&file_path).await,
+        ) else {
+            return Ok(status_response(StatusCode::NOT_FOUND));
+        };
+        if !file_path.starts_with(&root) {
+            println!("[static] refused path (outside of root): {}", path);
+            return Ok(status_response(StatusCode::FORBIDDEN));
+        }
+
+        let metadata = tokio::fs::metadata(&file_path).await?;
+        if metadata.is_dir() {
+            // relative links in an index / listing need a trailing slash
+            if !req.uri.path().ends_with('/') {
+                let location = format!("{}/", req.uri.path());
+                let mut response = status_response(StatusCode::MOVED_PERMANENTLY);
+                response
+                    .headers_mut()
+                    .insert(header::LOCATION, HeaderValue::from_str(&location)?);
+                return Ok(response);
+            }
+            let index = file_path.join("index.html");
+            if let Ok(metadata) = tokio::fs::metadata(&index).await {
+                return self.serve_file(req, &index, &metadata).await;
+            }
+            if self.listing {
+                return list_directory(req, &file_path).await;
+            }
+            return Ok(status_response(StatusCode::NOT_FOUND));
+        }
+        self.serve_file(req, &file_path, &metadata).await
+    }
+
+    async fn serve_file(
+        &self,
+        req: &Parts,
+        path: &Path,
+        metadata: &std::fs::Metadata,
+    ) -> HandlerResult {
+        let size = metadata.len();
+        let modified = metadata.modified().ok().map(truncate_to_secs);
+        let etag = etag(size, modified);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ETAG, HeaderValue::from_str(&etag)?);
+        if let Some(modified) = modified {
+            headers.insert(
+                header::LAST_MODIFIED,
+                HeaderValue::from_str(&httpdate::fmt_http_date(modified))?,
+            );
+        }
+        headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+        if !modified_since(&req.headers, &etag, modified) {
+            let mut response = status_response(StatusCode::NOT_MODIFIED);
+            response.headers_mut().extend(headers);
+            return Ok(response);
+        }
+
+        let mime = mime_guess::from_path(path).first_or_octet_stream();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(mime.as_ref())?);
+
+        let range = match req.headers.get(header::RANGE) {
+            Some(range) if if_range_matches(&req.headers, &etag) => {
+                parse_range(range.to_str().unwrap_or_default(), size)
+            }
+            _ => RangeRequest::Full,
+        };
+        let (status, range) = match range {
+            RangeRequest::Full => (StatusCode::OK, None),
+            RangeRequest::Partial(range) => {
+                headers.insert(
+                    header::CONTENT_RANGE,
+                    HeaderValue::from_str(&format!(
+                        "bytes {}-{}/{}",
+                        range.start, range.end, size
+                    ))?,
+                );
+                (StatusCode::PARTIAL_CONTENT, Some(range))
+            }
+            RangeRequest::Unsatisfiable => {
+                let mut response = status_response(StatusCode::RANGE_NOT_SATISFIABLE);
+                response.headers_mut().insert(
+                    header::CONTENT_RANGE,
+                    HeaderValue::from_str(&format!("bytes */{}", size))?,
+                );
+                return Ok(response);
+            }
+        };
+        let (offset, len) = match range {
+            Some(range) => (range.start, range.end - range.start + 1),
+            None => (0, size),
+        };
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(len));
+
+        let body = if req.method == Method::HEAD {
+            empty()
+        } else {
+            let mut file = tokio::fs::File::open(path).await?;
+            file.seek(std::io::SeekFrom::Start(offset)).await?;
+            // take: only stream the requested range
+            let stream = ReaderStream::with_capacity(file.take(len), self.chunk_size)
+                .map_ok(Frame::data)
+                .map_err(|e| -> BoxError { Box::new(e) });
+            BoxedBody::new(StreamBody::new(stream))
+        };
+
+        let mut response = Response::new(body);
+        *response.status_mut() = status;
+        response.headers_mut().extend(headers);
+        Ok(response)
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<BoxedBody> {
+    let mut response = Response::new(empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// Http dates have a 1s resolution
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => UNIX_EPOCH + Duration::from_secs(d.as_secs()),
+        Err(_) => time,
+    }
+}
+
+/// A validator computed from the file size & modification time (no need to read the file)
+fn etag(size: u64, modified: Option<SystemTime>) -> String {
+    let secs = modified
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", size, secs)
+}
+
+/// Does an If-None-Match header value match the etag (weak comparison)
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|tag| {
+        tag == "*"
+            || tag.strip_prefix("W/").unwrap_or(tag) == etag.strip_prefix("W/").unwrap_or(etag)
+    })
+}
+
+/// Return false if the client cache is up to date (304 Not Modified)
+///
+/// If-None-Match takes precedence over If-Modified-Since
+fn modified_since(headers: &HeaderMap, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        return !etag_matches(if_none_match.to_str().unwrap_or_default(), etag);
+    }
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok());
+    match (since, modified) {
+        (Some(since), Some(modified)) => modified > since,
+        _ => true,
+    }
+}
+
+/// If-Range: only honour the Range header if the file has not changed
+fn if_range_matches(headers: &HeaderMap, etag: &str) -> bool {
+    match headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) {
+        // strong comparison
+        Some(if_range) => if_range == etag,
+        None => true,
+    }
+}
+
+/// Parse a Range header (a single range, e.g. bytes=0-99, bytes=100- or bytes=-100)
+///
+/// Multiple ranges are not supported: the whole file is served
+pub fn parse_range(range: &str, size: u64) -> RangeRequest {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // bytes=-N: the last N bytes
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 || size == 0 {
+                return RangeRequest::Unsatisfiable;
+            }
+            ByteRange {
+                start: size.saturating_sub(suffix),
+                end: size - 1,
+            }
+        }
+        // bytes=N-
+        (Ok(start), Err(_)) if end.is_empty() => ByteRange {
+            start,
+            end: size.saturating_sub(1),
+        },
+        (Ok(start), Ok(end)) if start <= end => ByteRange {
+            start,
+            end: end.min(size.saturating_sub(1)),
+        },
+        // invalid syntax: ignore the header
+        _ => return RangeRequest::Full,
+    };
+    if range.start >= size {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(range)
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+async fn list_directory(req: &Parts, dir: &Path) -> HandlerResult {
+    let mut entries = vec![];
+    let mut read_dir = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_dir = entry.file_type().await?.is_dir();
+        entries.push((name, is_dir));
+    }
+    entries.sort();
+
+    let title = html_escape(req.uri.path());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><title>Index of {0}</title></head><body>\n<h1>Index of {0}</h1>\n<ul>\n<li><a href=\"../\">../</a></li>\n",
+        title
+    );
+    for (name, is_dir) in entries {
+        let suffix = if is_dir { "/" } else { "" };
+        let href = utf8_percent_encode(&name, PATH_SEGMENT);
+        html.push_str(&format!(
+            "<li><a href=\"{}{}\">{}{}</a></li>\n",
+            href,
+            suffix,
+            html_escape(&name),
+            suffix
+        ));
+    }
+    html.push_str("</ul>\n</body></html>\n");
+
+    let mut response = Response::new(if req.method == Method::HEAD {
+        empty()
+    } else {
+        full(html)
+    });
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let files = StaticFiles::new("/srv/www");
+        assert_eq!(
+            files.resolve("css/site.css"),
+            Some(PathBuf::from("/srv/www/css/site.css"))
+        );
+        assert_eq!(
+            files.resolve("a%20b/./c.txt"),
+            Some(PathBuf::from("/srv/www/a b/c.txt"))
+        );
+        assert_eq!(files.resolve(""), Some(PathBuf::from("/srv/www")));
+        assert_eq!(files.resolve("../etc/passwd"), None);
+        assert_eq!(files.resolve("a/%2e%2e/%2e%2e/etc/passwd"), None);
+        // an encoded slash is a separator: stays under root
+        assert_eq!(
+            files.resolve("%2Fetc%2Fpasswd"),
+            Some(PathBuf::from("/srv/www/etc/passwd"))
+        );
+        assert_eq!(files.resolve("..%5c..%5cwindows"), None);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let r = |start, end| RangeRequest::Partial(ByteRange { start, end });
+        assert_eq!(parse_range("bytes=0-99", 1000), r(0, 99));
+        assert_eq!(parse_range("bytes=900-", 1000), r(900, 999));
+        assert_eq!(parse_range("bytes=-100", 1000), r(900, 999));
+        assert_eq!(parse_range("bytes=-2000", 1000), r(0, 999));
+        assert_eq!(parse_range("bytes=990-2000", 1000), r(990, 999));
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-9,20-29", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=9-0", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("items=0-9", 1000), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("\"a-1\"", "\"a-1\""));
+        assert!(etag_matches("\"b-2\", W/\"a-1\"", "\"a-1\""));
+        assert!(etag_matches("*", "\"a-1\""));
+        assert!(!etag_matches("\"a-2\"", "\"a-1\""));
+    }
+}
@@ -0,0 +1,335 @@
+//! multipart/form-data uploads: the body is parsed as a stream (multer), each file part is
+//! written to a directory as it arrives (never fully buffered in memory)
+//!
+//! The response is a JSON summary: fields (name & value), files (name, filename, size,
+//! SHA-256 digest & stored path).
+//!
+//! * malformed request (no or bad boundary, truncated body...): 400 with the parsing error
+//! * a part larger than `max_part_size` or a body larger than `max_total_size`: 413
+//!
+//! On error, the files already written by the request are removed.
+
+use std::path::{Path, PathBuf};
+
+use http_body_util::BodyExt;
+use hyper::body::Incoming;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Request, Response, StatusCode};
+use multer::{Constraints, Multipart, SizeLimit};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tls_config_lib::to_hex_with;
+use tokio::io::AsyncWriteExt;
+
+use crate::body::full;
+use crate::router::HandlerResult;
+
+const DEFAULT_MAX_PART_SIZE: u64 = 4 * 1024 * 1024;
+const DEFAULT_MAX_TOTAL_SIZE: u64 = 10 * 1024 * 1024;
+/// Max length of a stored file name (without the unique prefix)
+const MAX_FILE_NAME_LEN: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct Uploads {
+    dir: PathBuf,
+    max_part_size: u64,
+    max_total_size: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct UploadSummary {
+    pub fields: Vec<FieldSummary>,
+    pub files: Vec<FileSummary>,
+    pub total_size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldSummary {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileSummary {
+    pub name: String,
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub size: u64,
+    pub sha256: String,
+    pub path: PathBuf,
+}
+
+/// A file name safe to use in the upload directory: the last path component, only
+/// alphanumeric characters, '.', '-' & '_' (others are replaced by '_')
+fn sanitize_file_name(name: &str) -> String {
+    // both separators (the file name comes from any client os)
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let sanitized: String = base
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') => c,
+            _ => '_',
+        })
+        .take(MAX_FILE_NAME_LEN)
+        .collect();
+    match sanitized.trim_start_matches('.') {
+        "" => "upload".to_string(),
+        // no hidden files
+        s => s.to_string(),
+    }
+}
+
+fn json_response(status: StatusCode, value: &impl Serialize) -> HandlerResult {
+    let mut response = Response::new(full(serde_json::to_vec_pretty(value)?));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(response)
+}
+
+fn error_response(status: StatusCode, message: String) -> HandlerResult {
+    #[derive(Serialize)]
+    struct ErrorMessage {
+        error: String,
+    }
+    json_response(status, &ErrorMessage { error: message })
+}
+
+impl Uploads {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: dir.into(),
+            max_part_size: DEFAULT_MAX_PART_SIZE,
+            max_total_size: DEFAULT_MAX_TOTAL_SIZE,
+        }
+    }
+
+    pub fn max_part_size(mut self, max_part_size: u64) -> Self {
+        self.max_part_size = max_part_size;
+        self
+    }
+
+    pub fn max_total_size(mut self, max_total_size: u64) -> Self {
+        self.max_total_size = max_total_size;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// POST /upload
+    pub async fn handle(&self, req: Request<Incoming>) -> HandlerResult {
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        let boundary = match multer::parse_boundary(content_type) {
+            Ok(boundary) => boundary,
+            Err(e) => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    format!(
+                        "Expected a multipart/form-data request with a boundary: {}",
+                        e
+                    ),
+                )
+            }
+        };
+
+        let constraints = Constraints::new().size_limit(
+            SizeLimit::new()
+                .whole_stream(self.max_total_size)
+                .per_field(self.max_part_size),
+        );
+        let stream = req.into_body().into_data_stream();
+        let mut multipart = Multipart::with_constraints(stream, boundary, constraints);
+
+        let mut written = vec![];
+        let result = self.read_parts(&mut multipart, &mut written).await;
+        let error = match result {
+            Ok(summary) => return json_response(StatusCode::OK, &summary),
+            Err(e) => e,
+        };
+
+        // incomplete upload: remove the files written so far
+        for path in written {
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                eprintln!("[upload] unable to remove {}: {}", path.display(), e);
+            }
+        }
+        match error {
+            UploadError::Multipart(
+                e @ (multer::Error::FieldSizeExceeded { .. }
+                | multer::Error::StreamSizeExceeded { .. }),
+            ) => error_response(StatusCode::PAYLOAD_TOO_LARGE, e.to_string()),
+            // client gone, connection error...
+            UploadError::Multipart(multer::Error::StreamReadFailed(e)) => Err(e),
+            UploadError::Multipart(e) => error_response(
+                StatusCode::BAD_REQUEST,
+                format!("Malformed multipart/form-data body: {}", e),
+            ),
+            UploadError::Io(e) => Err(Box::new(e)),
+        }
+    }
+
+    async fn read_parts(
+        &self,
+        multipart: &mut Multipart<'_>,
+        written: &mut Vec<PathBuf>,
+    ) -> Result<UploadSummary, UploadError> {
+        let mut summary = UploadSummary::default();
+        while let Some(mut field) = multipart.next_field().await? {
+            let name = field.name().unwrap_or_default().to_string();
+            let Some(filename) = field.file_name().map(|f| f.to_string()) else {
+                // not a file: a (small) form field
+                let value = field.bytes().await?;
+                summary.total_size += value.len() as u64;
+                summary.fields.push(FieldSummary {
+                    name,
+                    value: String::from_utf8_lossy(&value).into_owned(),
+                });
+                continue;
+            };
+
+            // unique name: several parts (or requests) can have the same file name
+            let id: [u8; 4] = rand::random();
+            let path = self.dir.join(format!(
+                "{}-{}",
+                to_hex_with(&id, ""),
+                sanitize_file_name(&filename)
+            ));
+            let mut file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await?;
+            written.push(path.clone());
+
+            let mut hasher = Sha256::new();
+            let mut size: u64 = 0;
+            while let Some(chunk) = field.chunk().await? {
+                hasher.update(&chunk);
+                size += chunk.len() as u64;
+                file.write_all(&chunk).await?;
+            }
+            file.flush().await?;
+            println!(
+                "[upload] {} ({} bytes) -> {}",
+                filename,
+                size,
+                path.display()
+            );
+
+            summary.total_size += size;
+            summary.files.push(FileSummary {
+                name,
+                filename,
+                content_type: field.content_type().map(|m| m.to_string()),
+                size,
+                sha256: to_hex_with(&hasher.finalize(), ""),
+                path,
+            });
+        }
+        Ok(summary)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum UploadError {
+    #[error(transparent)]
+    Multipart(#[from] multer::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use futures::stream;
+
+    fn multipart_body(boundary: &str, parts: &[(&str, Option<&str>, &[u8])]) -> Bytes {
+        let mut body = vec![];
+        for (name, filename, data) in parts {
+            body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            match filename {
+                Some(filename) => body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+                         Content-Type: application/octet-stream\r\n\r\n",
+                        name, filename
+                    )
+                    .as_bytes(),
+                ),
+                None => body.extend_from_slice(
+                    format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes(),
+                ),
+            }
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+        Bytes::from(body)
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("photo.jpg"), "photo.jpg");
+        assert_eq!(sanitize_file_name("../../etc/passwd"), "passwd");
+        assert_eq!(
+            sanitize_file_name("C:\\Users\\me\\my file.txt"),
+            "my_file.txt"
+        );
+        assert_eq!(sanitize_file_name(".bashrc"), "bashrc");
+        assert_eq!(sanitize_file_name(".."), "upload");
+        assert_eq!(sanitize_file_name(""), "upload");
+    }
+
+    #[tokio::test]
+    async fn test_read_parts() {
+        let dir = std::env::temp_dir().join(format!("upload_test_{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let uploads = Uploads::new(&dir).max_part_size(16);
+
+        let body = multipart_body(
+            "XyZ",
+            &[("title", None, b"hello"), ("file", Some("a.txt"), b"abc")],
+        );
+        let stream = stream::iter(vec![Ok::<_, std::io::Error>(body)]);
+        let mut multipart = Multipart::new(stream, "XyZ");
+        let mut written = vec![];
+        let summary = uploads
+            .read_parts(&mut multipart, &mut written)
+            .await
+            .unwrap();
+        assert_eq!(summary.fields[0].value, "hello");
+        assert_eq!(summary.files[0].size, 3);
+        assert_eq!(
+            summary.files[0].sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(tokio::fs::read(&written[0]).await.unwrap(), b"abc");
+        assert_eq!(summary.total_size, 8);
+
+        // part too large
+        let body = multipart_body("XyZ", &[("file", Some("b.txt"), &[0u8; 17])]);
+        let stream = stream::iter(vec![Ok::<_, std::io::Error>(body)]);
+        let mut multipart = Multipart::with_constraints(
+            stream,
+            "XyZ",
+            Constraints::new().size_limit(SizeLimit::new().per_field(16)),
+        );
+        let result = uploads.read_parts(&mut multipart, &mut written).await;
+        assert!(matches!(
+            result,
+            Err(UploadError::Multipart(
+                multer::Error::FieldSizeExceeded { .. }
+            ))
+        ));
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+//! WebSocket echo: the http connection is upgraded (101 Switching Protocols) then the
+//! upgraded connection is handled by tokio-tungstenite
+//!
+//! * text message: echoed uppercased
+//! * binary message: echoed reversed
+//! * ping: answered with a pong (by tungstenite)
+//! * close: answered with the same close code, text message "bye" or idle timeout: the
+//!   server closes the connection (1000 Normal / 1001 Away)
+
+use std::time::Duration;
+
+use futures::{SinkExt, StreamExt};
+use hyper::body::Incoming;
+use hyper::header::{
+    HeaderMap, HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY,
+    SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::body::{empty, full};
+use crate::router::{HandlerResult, Params};
+
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn header_contains(headers: &HeaderMap, name: hyper::header::HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case(token))
+}
+
+/// Is this a (valid) WebSocket upgrade request (RFC 6455: GET, Connection: Upgrade,
+/// Upgrade: websocket & a key)
+pub fn is_upgrade_request<B>(req: &Request<B>) -> bool {
+    req.method() == Method::GET
+        && header_contains(req.headers(), CONNECTION, "upgrade")
+        && header_contains(req.headers(), UPGRADE, "websocket")
+        && req.headers().contains_key(SEC_WEBSOCKET_KEY)
+}
+
+/// GET /ws
+pub async fn ws_handler(mut req: Request<Incoming>, _params: Params) -> HandlerResult {
+    if !is_upgrade_request(&req) {
+        let mut response = Response::new(full("Expected a WebSocket upgrade request\n"));
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        return Ok(response);
+    }
+    if req.headers().get(SEC_WEBSOCKET_VERSION) != Some(&HeaderValue::from_static("13")) {
+        let mut response = Response::new(empty());
+        *response.status_mut() = StatusCode::UPGRADE_REQUIRED;
+        response
+            .headers_mut()
+            .insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+        return Ok(response);
+    }
+
+    let key = req.headers()[SEC_WEBSOCKET_KEY].as_bytes();
+    let accept = derive_accept_key(key);
+
+    // The upgrade completes once the 101 response has been sent
+    let on_upgrade = hyper::upgrade::on(&mut req);
+    tokio::spawn(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => {
+                let ws =
+                    WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None)
+                        .await;
+                if let Err(e) = echo_session(ws).await {
+                    eprintln!("[ws] error: {}", e);
+                }
+            }
+            Err(e) => eprintln!("[ws] upgrade error: {}", e),
+        }
+    });
+
+    let mut response = Response::new(empty());
+    *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    let headers = response.headers_mut();
+    headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+    headers.insert(SEC_WEBSOCKET_ACCEPT, HeaderValue::from_str(&accept)?);
+    Ok(response)
+}
+
+fn close_message(code: CloseCode, reason: &str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+/// Echo the messages of a WebSocket connection until it is closed
+pub async fn echo_session<S>(
+    mut ws: WebSocketStream<S>,
+) -> Result<(), tokio_tungstenite::tungstenite::Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    println!("[ws] connection opened");
+    loop {
+        let message = match tokio::time::timeout(IDLE_TIMEOUT, ws.next()).await {
+            Ok(Some(message)) => message?,
+            // connection closed (without a close frame)
+            Ok(None) => break,
+            Err(_) => {
+                println!("[ws] idle timeout, closing");
+                ws.send(close_message(CloseCode::Away, "idle timeout"))
+                    .await?;
+                // wait (a bit) for the close reply of the client
+                let drain = async { while let Some(Ok(_)) = ws.next().await {} };
+                let _ = tokio::time::timeout(CLOSE_TIMEOUT, drain).await;
+                break;
+            }
+        };
+        match message {
+            Message::Text(text) if text.as_str() == "bye" => {
+                // the client answers with a close frame, then the stream ends
+                ws.send(close_message(CloseCode::Normal, "bye")).await?;
+            }
+            Message::Text(text) => {
+                ws.send(Message::text(text.as_str().to_uppercase())).await?;
+            }
+            Message::Binary(data) => {
+                let reversed: Vec<u8> = data.iter().rev().cloned().collect();
+                ws.send(Message::binary(reversed)).await?;
+            }
+            // the pong is queued by tungstenite & sent with the next write / flush
+            Message::Ping(payload) => {
+                println!("[ws] ping ({} bytes)", payload.len());
+                ws.flush().await?;
+            }
+            Message::Pong(_) => {}
+            // tungstenite replies with the same close code
+            Message::Close(frame) => {
+                println!("[ws] close received: {:?}", frame);
+            }
+            Message::Frame(_) => {}
+        }
+    }
+    println!("[ws] connection closed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_upgrade_request() {
+        let req = Request::get("/ws")
+            .header(CONNECTION, "keep-alive, Upgrade")
+            .header(UPGRADE, "websocket")
+            .header(SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+            .body(())
+            .unwrap();
+        assert!(is_upgrade_request(&req));
+
+        let req = Request::get("/ws")
+            .header(UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        assert!(!is_upgrade_request(&req));
+    }
+
+    #[tokio::test]
+    async fn test_echo_session() {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let ws = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+            echo_session(ws).await.unwrap();
+        });
+        let mut ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+
+        ws.send(Message::text("hello")).await.unwrap();
+        assert_eq!(ws.next().await.unwrap().unwrap(), Message::text("HELLO"));
+        ws.send(Message::binary(vec![1, 2, 3])).await.unwrap();
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::binary(vec![3, 2, 1])
+        );
+        ws.send(Message::Ping("ping".into())).await.unwrap();
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            Message::Pong("ping".into())
+        );
+        ws.send(Message::text("bye")).await.unwrap();
+        match ws.next().await.unwrap().unwrap() {
+            Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Normal),
+            m => panic!("Expected a close frame, got: {:?}", m),
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! The echo server routes, served on an ephemeral port and called with the client module
+
+use std::future::pending;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::Bytes;
+use hyper::{Request, StatusCode};
+use tokio::task::JoinHandle;
+
+use hyper_01_http_post::client::{ClientError, HttpClient};
+use hyper_01_http_post::server::{Server, ServerConfig, ShutdownHandle};
+use hyper_01_http_post::shutdown::ShutdownReason;
+
+const ADMIN_TOKEN: &str = "s3cret";
+
+struct TestServer {
+    addr: SocketAddr,
+    handle: ShutdownHandle,
+    task: JoinHandle<ShutdownReason>,
+    client: HttpClient,
+}
+
+impl TestServer {
+    async fn start() -> Self {
+        let config = ServerConfig {
+            admin_token: Some(ADMIN_TOKEN.to_string()),
+            drain_timeout: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let server = Server::bind("127.0.0.1:0".parse().unwrap(), config)
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        // no os signal: stopped by /stop or the shutdown handle
+        let task = tokio::spawn(server.run(pending()));
+        let client = HttpClient::builder().max_retries(0).build().unwrap();
+        Self {
+            addr,
+            handle,
+            task,
+            client,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    async fn stop(self) -> ShutdownReason {
+        self.handle.shutdown().await;
+        self.task.await.unwrap()
+    }
+}
+
+#[tokio::test]
+async fn test_index() {
+    let server = TestServer::start().await;
+    let resp = server.client.get(&server.url("/")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.body().starts_with(b"Try POST'ing data to /echo"));
+
+    // HEAD is answered by the GET route, without body
+    let req = Request::head(server.url("/")).body(Bytes::new()).unwrap();
+    let resp = server.client.send(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.body().is_empty());
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_echo() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let resp = client
+        .post(&server.url("/echo"), "hello world")
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body(), "hello world");
+
+    let resp = client
+        .post(&server.url("/echo/uppercase"), "hello world")
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body(), "HELLO WORLD");
+
+    let resp = client
+        .post(&server.url("/echo/reversed"), "hello world")
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body(), "dlrow olleh");
+
+    // empty body
+    let resp = client.post(&server.url("/echo"), "").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.body().is_empty());
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_not_found() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    let resp = client.get(&server.url("/nowhere")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // unknown transform
+    let resp = client
+        .post(&server.url("/echo/sideways"), "hello world")
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // known path, wrong method
+    let resp = client.get(&server.url("/echo")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_stop() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+    let stop = |token: &str, body: &'static str| {
+        Request::post(server.url("/stop"))
+            .header("Authorization", format!("Bearer {}", token))
+            .body(Bytes::from(body))
+            .unwrap()
+    };
+
+    let resp = client.send(stop("wrong", "1")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // empty body: the server keeps running
+    let resp = client.send(stop(ADMIN_TOKEN, "")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body(), "Not stopping (empty body)\n");
+    let resp = client.get(&server.url("/")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = client.send(stop(ADMIN_TOKEN, "1")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.body().starts_with(b"Thanks for that!!"));
+
+    let reason = tokio::time::timeout(Duration::from_secs(5), server.task)
+        .await
+        .expect("the server did not stop")
+        .unwrap();
+    assert_eq!(reason, ShutdownReason::Admin);
+
+    // the listener is closed
+    let res = HttpClient::builder()
+        .max_retries(0)
+        .build()
+        .unwrap()
+        .get(&format!("http://{}/", server.addr))
+        .await;
+    assert!(
+        matches!(res, Err(ClientError::ConnectionRefused(_))),
+        "{:?}",
+        res
+    );
+}
+
+#[tokio::test]
+async fn test_echo_json() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+    let json = |content_type: &str, accept: &str, body: &'static str| {
+        Request::post(server.url("/echo/json"))
+            .header("Content-Type", content_type)
+            .header("Accept", accept)
+            .body(Bytes::from(body))
+            .unwrap()
+    };
+
+    let resp = client
+        .send(json(
+            "application/json",
+            "application/json",
+            r#"{ "library": "hyper" }"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["content-type"], "application/json");
+    assert_eq!(resp.body(), r#"{"library":"hyper"}"#);
+
+    let resp = client
+        .send(json(
+            "application/json",
+            "application/yaml",
+            r#"{"library":"hyper"}"#,
+        ))
+        .await
+        .unwrap();
+    assert_eq!(resp.headers()["content-type"], "application/yaml");
+    assert_eq!(resp.body(), "library: hyper\n");
+
+    let resp = client
+        .send(json("application/yaml", "*/*", "library: hyper\n"))
+        .await
+        .unwrap();
+    assert_eq!(resp.body(), "{\n  \"library\": \"hyper\"\n}\n");
+
+    // invalid body: error with position
+    let resp = client
+        .send(json("application/json", "*/*", "{\"library\":\n  hyper}"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let error: serde_json::Value = serde_json::from_slice(resp.body()).unwrap();
+    assert_eq!(error["line"], 2);
+    assert_eq!(error["column"], 3);
+
+    let resp = client
+        .send(json("text/plain", "*/*", "hello"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let resp = client
+        .send(json("application/json", "text/html", "{}"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_ACCEPTABLE);
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn test_metrics() {
+    let server = TestServer::start().await;
+    let client = &server.client;
+
+    for method in ["PURGE", "FOO"] {
+        let req = Request::builder()
+            .method(method)
+            .uri(server.url("/"))
+            .body(Bytes::new())
+            .unwrap();
+        let resp = client.send(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    let resp = client.get(&server.url("/metrics")).await.unwrap();
+    let text = String::from_utf8_lossy(resp.body());
+    // extension methods share one series
+    assert!(
+        text.contains(r#"http_requests_total{method="other",route="unmatched",status="405"} 2"#),
+        "{}",
+        text
+    );
+    assert!(!text.contains("PURGE") && !text.contains("FOO"));
+
+    server.stop().await;
+}
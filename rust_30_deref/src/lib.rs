@@ -0,0 +1,31 @@
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::ops::DerefMut;
+
+/// A key value store: all the BTreeMap methods are available through Deref / DerefMut
+#[derive(Debug, Default)]
+pub struct Datastore {
+    inner: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Datastore {
+    pub fn new() -> Self {
+        Self {
+            inner: Default::default(),
+        }
+    }
+}
+
+impl Deref for Datastore {
+    type Target = BTreeMap<Vec<u8>, Vec<u8>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for Datastore {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
@@ -1,35 +1,4 @@
-use std::collections::BTreeMap;
-use std::ops::Deref;
-use std::ops::DerefMut;
-
-#[derive(Debug)]
-struct Datastore {
-    inner: BTreeMap<Vec<u8>, Vec<u8>>,
-}
-
-impl Datastore {
-    fn new() -> Self {
-        Self {
-            inner: Default::default(),
-        }
-    }
-}
-
-impl Deref for Datastore {
-    type Target = BTreeMap<Vec<u8>, Vec<u8>>;
-
-    fn deref(&self) -> &Self::Target {
-        println!("[Debug] Datastore deref");
-        &self.inner
-    }
-}
-
-impl DerefMut for Datastore {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        println!("[Debug] Datastore deref mut");
-        &mut self.inner
-    }
-}
+use rust_30_deref::Datastore;
 
 fn main() {
     println!("Building a new data store:");
@@ -0,0 +1,646 @@
+// Exif metadata (JPEG APP1 segment): "Exif\0\0" then a TIFF structure
+// * TIFF header: byte order ("II": little endian, "MM": big endian), 42, offset of IFD0
+// * IFD (Image File Directory): entry count (u16), entries (12 bytes each), offset of next IFD
+// * entry: tag (u16), type (u16), count (u32), value (if <= 4 bytes) or offset of the value
+//
+// IFD0 (main image) points to the Exif IFD & GPS IFD (pointer tags), its next IFD is IFD1
+// (thumbnail). All offsets are relative to the TIFF header and are checked: a malformed
+// offset is an error (never a panic).
+//
+// https://www.media.mit.edu/pia/Research/deepview/exif.html
+// https://exiftool.org/TagNames/EXIF.html
+
+use std::fmt;
+
+use nom::bytes::complete::take;
+use nom::multi::count;
+use nom::number::complete::{i32, u16, u32};
+use nom::number::Endianness;
+use nom::{IResult, Parser};
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const TIFF_MAGIC: u16 = 42;
+const IFD_ENTRY_SIZE: usize = 12;
+
+const EXIF_IFD_POINTER: u16 = 0x8769;
+const GPS_IFD_POINTER: u16 = 0x8825;
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ExifError {
+    #[error("Not an Exif segment (no Exif header)")]
+    NotExif,
+    #[error("Invalid byte order: {0:?}")]
+    InvalidByteOrder([u8; 2]),
+    #[error("Invalid TIFF magic number: {0}")]
+    InvalidMagic(u16),
+    #[error("Offset out of bounds: {offset} (+ {len} bytes), TIFF data: {size} bytes")]
+    InvalidOffset { offset: u32, len: u64, size: usize },
+    #[error("Invalid IFD pointer (tag: {0:#06x})")]
+    InvalidPointer(u16),
+    #[error("IFD loop (offset {0} already read)")]
+    IfdLoop(u32),
+    #[error("Parse error: {0:?}")]
+    Nom(nom::error::ErrorKind),
+}
+
+impl From<nom::Err<nom::error::Error<&[u8]>>> for ExifError {
+    fn from(e: nom::Err<nom::error::Error<&[u8]>>) -> Self {
+        match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => ExifError::Nom(e.code),
+            nom::Err::Incomplete(_) => ExifError::Nom(nom::error::ErrorKind::Eof),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ifd {
+    Ifd0, // main image
+    Exif,
+    Gps,
+    Ifd1, // thumbnail
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(Vec<u8>),
+    Ascii(String),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    SRational(Vec<(i32, i32)>),
+    Undefined(Vec<u8>),
+    // other types (SBYTE, SSHORT, SLONG, FLOAT, DOUBLE): not decoded
+    Unknown { field_type: u16, count: u32 },
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T: fmt::Display>(values: &[T]) -> String {
+            values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        match self {
+            // could be large (e.g. MakerNote): only the size
+            Value::Byte(v) | Value::Undefined(v) if v.len() > 16 => write!(f, "{} bytes", v.len()),
+            Value::Byte(v) | Value::Undefined(v) => write!(f, "{:?}", v),
+            Value::Ascii(s) => write!(f, "{:?}", s),
+            Value::Short(v) => write!(f, "{}", list(v)),
+            Value::Long(v) => write!(f, "{}", list(v)),
+            Value::Rational(v) => write!(f, "{}", list(&rationals(v))),
+            Value::SRational(v) => write!(f, "{}", list(&rationals(v))),
+            Value::Unknown { field_type, count } => {
+                write!(f, "type {} (count: {})", field_type, count)
+            }
+        }
+    }
+}
+
+fn rationals<T: fmt::Display>(values: &[(T, T)]) -> Vec<String> {
+    values.iter().map(|(n, d)| format!("{}/{}", n, d)).collect()
+}
+
+impl Value {
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Ascii(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_u16(&self) -> Option<u16> {
+        match self {
+            Value::Short(v) => v.first().copied(),
+            _ => None,
+        }
+    }
+
+    fn as_f64s(&self) -> Option<Vec<f64>> {
+        match self {
+            Value::Rational(v) => v
+                .iter()
+                .map(|(n, d)| (*d != 0).then(|| *n as f64 / *d as f64))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub ifd: Ifd,
+    pub tag: u16,
+    pub value: Value,
+}
+
+impl Entry {
+    pub fn name(&self) -> Option<&'static str> {
+        tag_name(self.ifd, self.tag)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsCoordinates {
+    pub latitude: f64,         // degrees, negative: south
+    pub longitude: f64,        // degrees, negative: west
+    pub altitude: Option<f64>, // meters, negative: below sea level
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Exif {
+    pub byte_order: Endianness,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    // 1: normal, 3: rotated 180, 6: rotated 90 CW, 8: rotated 90 CCW (2, 4, 5, 7: mirrored)
+    pub orientation: Option<u16>,
+    // "YYYY:MM:DD HH:MM:SS" (last modification)
+    pub date_time: Option<String>,
+    pub date_time_original: Option<String>,
+    pub date_time_digitized: Option<String>,
+    pub gps: Option<GpsCoordinates>,
+    pub entries: Vec<Entry>,
+}
+
+impl Exif {
+    pub fn get(&self, ifd: Ifd, tag: u16) -> Option<&Value> {
+        self.entries
+            .iter()
+            .find(|e| e.ifd == ifd && e.tag == tag)
+            .map(|e| &e.value)
+    }
+
+    fn get_str(&self, ifd: Ifd, tag: u16) -> Option<String> {
+        self.get(ifd, tag)?.as_str().map(|s| s.to_string())
+    }
+
+    fn gps_coordinates(&self) -> Option<GpsCoordinates> {
+        // degrees, minutes, seconds & a reference (N/S, E/W)
+        let coordinate = |tag: u16, ref_tag: u16, negative: &str| {
+            let dms = self.get(Ifd::Gps, tag)?.as_f64s()?;
+            let [d, m, s] = dms[..] else {
+                return None;
+            };
+            let value = d + m / 60.0 + s / 3600.0;
+            match self.get(Ifd::Gps, ref_tag)?.as_str()? {
+                r if r == negative => Some(-value),
+                _ => Some(value),
+            }
+        };
+        let altitude = self
+            .get(Ifd::Gps, 0x0006)
+            .and_then(|v| v.as_f64s())
+            .and_then(|v| v.first().copied())
+            .map(|altitude| match self.get(Ifd::Gps, 0x0005) {
+                Some(Value::Byte(r)) if r.first() == Some(&1) => -altitude,
+                _ => altitude,
+            });
+        Some(GpsCoordinates {
+            latitude: coordinate(0x0002, 0x0001, "S")?,
+            longitude: coordinate(0x0004, 0x0003, "W")?,
+            altitude,
+        })
+    }
+}
+
+/// Parse an APP1 segment (payload, after the segment size)
+pub fn parse(app1: &[u8]) -> Result<Exif, ExifError> {
+    let tiff = app1.strip_prefix(EXIF_HEADER).ok_or(ExifError::NotExif)?;
+    parse_tiff(tiff)
+}
+
+pub fn parse_tiff(tiff: &[u8]) -> Result<Exif, ExifError> {
+    let header = bytes_at(tiff, 0, 8)?;
+    let byte_order = match [header[0], header[1]] {
+        [b'I', b'I'] => Endianness::Little,
+        [b'M', b'M'] => Endianness::Big,
+        b => return Err(ExifError::InvalidByteOrder(b)),
+    };
+    let (header, magic) = u16(byte_order)(&header[2..])?;
+    if magic != TIFF_MAGIC {
+        return Err(ExifError::InvalidMagic(magic));
+    }
+    let (_, ifd0_offset) = u32(byte_order)(header)?;
+
+    let mut reader = IfdReader {
+        tiff,
+        endian: byte_order,
+        visited: vec![],
+    };
+    let mut entries = vec![];
+    let ifd1_offset = reader.read_ifd(ifd0_offset, Ifd::Ifd0, &mut entries)?;
+    // sub IFDs
+    for (pointer, ifd) in [(EXIF_IFD_POINTER, Ifd::Exif), (GPS_IFD_POINTER, Ifd::Gps)] {
+        let offset = match entries
+            .iter()
+            .find(|e| e.ifd == Ifd::Ifd0 && e.tag == pointer)
+        {
+            Some(Entry {
+                value: Value::Long(offset),
+                ..
+            }) if offset.len() == 1 => offset[0],
+            Some(_) => return Err(ExifError::InvalidPointer(pointer)),
+            None => continue,
+        };
+        reader.read_ifd(offset, ifd, &mut entries)?;
+    }
+    // Note: 0: no next IFD, the IFDs after IFD1 (if any) are not read
+    if ifd1_offset != 0 {
+        reader.read_ifd(ifd1_offset, Ifd::Ifd1, &mut entries)?;
+    }
+
+    let mut exif = Exif {
+        byte_order,
+        make: None,
+        model: None,
+        orientation: None,
+        date_time: None,
+        date_time_original: None,
+        date_time_digitized: None,
+        gps: None,
+        entries,
+    };
+    exif.make = exif.get_str(Ifd::Ifd0, 0x010F);
+    exif.model = exif.get_str(Ifd::Ifd0, 0x0110);
+    exif.orientation = exif.get(Ifd::Ifd0, 0x0112).and_then(|v| v.as_u16());
+    exif.date_time = exif.get_str(Ifd::Ifd0, 0x0132);
+    exif.date_time_original = exif.get_str(Ifd::Exif, 0x9003);
+    exif.date_time_digitized = exif.get_str(Ifd::Exif, 0x9004);
+    exif.gps = exif.gps_coordinates();
+    Ok(exif)
+}
+
+/// `len` bytes at `offset` in the TIFF data
+fn bytes_at(tiff: &[u8], offset: u32, len: u64) -> Result<&[u8], ExifError> {
+    let error = ExifError::InvalidOffset {
+        offset,
+        len,
+        size: tiff.len(),
+    };
+    let start = usize::try_from(offset).map_err(|_| error.clone())?;
+    let end = usize::try_from(len)
+        .ok()
+        .and_then(|len| start.checked_add(len))
+        .ok_or(error.clone())?;
+    tiff.get(start..end).ok_or(error)
+}
+
+/// Size of a value of the given type (None: unknown type)
+fn type_size(field_type: u16) -> Option<u64> {
+    match field_type {
+        1 | 2 | 6 | 7 => Some(1), // BYTE, ASCII, SBYTE, UNDEFINED
+        3 | 8 => Some(2),         // SHORT, SSHORT
+        4 | 9 | 11 => Some(4),    // LONG, SLONG, FLOAT
+        5 | 10 | 12 => Some(8),   // RATIONAL, SRATIONAL, DOUBLE
+        _ => None,
+    }
+}
+
+struct IfdReader<'a> {
+    tiff: &'a [u8],
+    endian: Endianness,
+    visited: Vec<u32>,
+}
+
+impl IfdReader<'_> {
+    /// Read the entries of an IFD, return the offset of the next IFD (0: none)
+    fn read_ifd(
+        &mut self,
+        offset: u32,
+        ifd: Ifd,
+        entries: &mut Vec<Entry>,
+    ) -> Result<u32, ExifError> {
+        if self.visited.contains(&offset) {
+            return Err(ExifError::IfdLoop(offset));
+        }
+        self.visited.push(offset);
+
+        let (_, entry_count) = u16(self.endian)(bytes_at(self.tiff, offset, 2)?)?;
+        let size = 2 + entry_count as u64 * IFD_ENTRY_SIZE as u64 + 4;
+        let data = bytes_at(self.tiff, offset, size)?;
+        let (mut data, _) = take(2usize)(data)?;
+        for _ in 0..entry_count {
+            let (rest, entry) = take(IFD_ENTRY_SIZE)(data)?;
+            data = rest;
+            let (tag, value) = self.read_entry(entry)?;
+            entries.push(Entry { ifd, tag, value });
+        }
+        let (_, next_offset) = u32(self.endian)(data)?;
+        Ok(next_offset)
+    }
+
+    fn read_entry(&self, entry: &[u8]) -> Result<(u16, Value), ExifError> {
+        let e = self.endian;
+        let (entry, (tag, field_type, value_count)) = (u16(e), u16(e), u32(e)).parse(entry)?;
+        let Some(size) = type_size(field_type) else {
+            let value = Value::Unknown {
+                field_type,
+                count: value_count,
+            };
+            return Ok((tag, value));
+        };
+        let len = value_count as u64 * size;
+        // the value itself if it fits in 4 bytes, else its offset
+        let data = match len <= 4 {
+            true => &entry[..len as usize],
+            false => {
+                let (_, offset) = u32(e)(entry)?;
+                bytes_at(self.tiff, offset, len)?
+            }
+        };
+        let n = value_count as usize;
+        let value = match field_type {
+            1 => Value::Byte(data.to_vec()),
+            2 => {
+                // null terminated (the count includes the \0)
+                let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+                Value::Ascii(String::from_utf8_lossy(&data[..end]).trim_end().to_string())
+            }
+            3 => Value::Short(values(data, count(u16(e), n))?),
+            4 => Value::Long(values(data, count(u32(e), n))?),
+            5 => Value::Rational(values(data, count((u32(e), u32(e)), n))?),
+            10 => Value::SRational(values(data, count((i32(e), i32(e)), n))?),
+            7 => Value::Undefined(data.to_vec()),
+            _ => Value::Unknown {
+                field_type,
+                count: value_count,
+            },
+        };
+        Ok((tag, value))
+    }
+}
+
+fn values<'a, T, P>(data: &'a [u8], mut parser: P) -> Result<T, ExifError>
+where
+    P: Parser<&'a [u8], Output = T, Error = nom::error::Error<&'a [u8]>>,
+{
+    let res: IResult<&[u8], T> = parser.parse(data);
+    Ok(res?.1)
+}
+
+pub fn tag_name(ifd: Ifd, tag: u16) -> Option<&'static str> {
+    let name = match (ifd, tag) {
+        (Ifd::Gps, 0x0000) => "GPSVersionID",
+        (Ifd::Gps, 0x0001) => "GPSLatitudeRef",
+        (Ifd::Gps, 0x0002) => "GPSLatitude",
+        (Ifd::Gps, 0x0003) => "GPSLongitudeRef",
+        (Ifd::Gps, 0x0004) => "GPSLongitude",
+        (Ifd::Gps, 0x0005) => "GPSAltitudeRef",
+        (Ifd::Gps, 0x0006) => "GPSAltitude",
+        (Ifd::Gps, 0x0007) => "GPSTimeStamp",
+        (Ifd::Gps, 0x0010) => "GPSImgDirectionRef",
+        (Ifd::Gps, 0x0011) => "GPSImgDirection",
+        (Ifd::Gps, 0x0012) => "GPSMapDatum",
+        (Ifd::Gps, 0x001D) => "GPSDateStamp",
+        (Ifd::Gps, _) => return None,
+        // IFD0, IFD1 & Exif IFD
+        (_, 0x0100) => "ImageWidth",
+        (_, 0x0101) => "ImageLength",
+        (_, 0x0103) => "Compression",
+        (_, 0x010E) => "ImageDescription",
+        (_, 0x010F) => "Make",
+        (_, 0x0110) => "Model",
+        (_, 0x0112) => "Orientation",
+        (_, 0x011A) => "XResolution",
+        (_, 0x011B) => "YResolution",
+        (_, 0x0128) => "ResolutionUnit",
+        (_, 0x0131) => "Software",
+        (_, 0x0132) => "DateTime",
+        (_, 0x013B) => "Artist",
+        (_, 0x0201) => "JPEGInterchangeFormat",
+        (_, 0x0202) => "JPEGInterchangeFormatLength",
+        (_, 0x0213) => "YCbCrPositioning",
+        (_, 0x8298) => "Copyright",
+        (_, 0x829A) => "ExposureTime",
+        (_, 0x829D) => "FNumber",
+        (_, 0x8769) => "ExifIFDPointer",
+        (_, 0x8822) => "ExposureProgram",
+        (_, 0x8825) => "GPSInfoIFDPointer",
+        (_, 0x8827) => "ISOSpeedRatings",
+        (_, 0x9000) => "ExifVersion",
+        (_, 0x9003) => "DateTimeOriginal",
+        (_, 0x9004) => "DateTimeDigitized",
+        (_, 0x9010) => "OffsetTime",
+        (_, 0x9011) => "OffsetTimeOriginal",
+        (_, 0x9201) => "ShutterSpeedValue",
+        (_, 0x9202) => "ApertureValue",
+        (_, 0x9204) => "ExposureBiasValue",
+        (_, 0x9207) => "MeteringMode",
+        (_, 0x9209) => "Flash",
+        (_, 0x920A) => "FocalLength",
+        (_, 0x927C) => "MakerNote",
+        (_, 0x9286) => "UserComment",
+        (_, 0x9290) => "SubSecTime",
+        (_, 0x9291) => "SubSecTimeOriginal",
+        (_, 0xA000) => "FlashpixVersion",
+        (_, 0xA001) => "ColorSpace",
+        (_, 0xA002) => "PixelXDimension",
+        (_, 0xA003) => "PixelYDimension",
+        (_, 0xA005) => "InteroperabilityIFDPointer",
+        (_, 0xA402) => "ExposureMode",
+        (_, 0xA403) => "WhiteBalance",
+        (_, 0xA405) => "FocalLengthIn35mmFilm",
+        (_, 0xA406) => "SceneCaptureType",
+        (_, 0xA434) => "LensModel",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build TIFF data: IFDs at fixed offsets (with their values after the entries)
+    struct TiffWriter {
+        endian: Endianness,
+        buf: Vec<u8>,
+    }
+
+    impl TiffWriter {
+        fn new(endian: Endianness) -> Self {
+            let mut w = Self {
+                endian,
+                buf: vec![0; 512],
+            };
+            let order = match endian {
+                Endianness::Little => b"II",
+                _ => b"MM",
+            };
+            w.buf[..2].copy_from_slice(order);
+            let header = [w.short(&[42]), w.long(&[8])].concat();
+            w.buf[2..8].copy_from_slice(&header);
+            w
+        }
+
+        fn short(&self, values: &[u16]) -> Vec<u8> {
+            let le = self.endian == Endianness::Little;
+            values
+                .iter()
+                .flat_map(|v| if le { v.to_le_bytes() } else { v.to_be_bytes() })
+                .collect()
+        }
+
+        fn long(&self, values: &[u32]) -> Vec<u8> {
+            let le = self.endian == Endianness::Little;
+            values
+                .iter()
+                .flat_map(|v| if le { v.to_le_bytes() } else { v.to_be_bytes() })
+                .collect()
+        }
+
+        fn rationals(&self, values: &[(u32, u32)]) -> Vec<u8> {
+            let longs: Vec<u32> = values.iter().flat_map(|(n, d)| [*n, *d]).collect();
+            self.long(&longs)
+        }
+
+        /// entries: (tag, type, count, value bytes)
+        fn ifd(&mut self, offset: usize, entries: &[(u16, u16, u32, Vec<u8>)], next: u32) {
+            let mut data_offset = offset + 2 + entries.len() * IFD_ENTRY_SIZE + 4;
+            let mut ifd = self.short(&[entries.len() as u16]);
+            for (tag, field_type, count, value) in entries {
+                ifd.extend(self.short(&[*tag, *field_type]));
+                ifd.extend(self.long(&[*count]));
+                if value.len() <= 4 {
+                    let mut inline = value.clone();
+                    inline.resize(4, 0);
+                    ifd.extend(inline);
+                } else {
+                    ifd.extend(self.long(&[data_offset as u32]));
+                    self.buf[data_offset..data_offset + value.len()].copy_from_slice(value);
+                    data_offset += value.len();
+                }
+            }
+            ifd.extend(self.long(&[next]));
+            self.buf[offset..offset + ifd.len()].copy_from_slice(&ifd);
+        }
+    }
+
+    fn sample(endian: Endianness) -> Vec<u8> {
+        let mut w = TiffWriter::new(endian);
+        let ifd0 = [
+            (0x010F, 2, 6, b"Canon\0".to_vec()),
+            (0x0110, 2, 4, b"EOS\0".to_vec()),
+            (0x0112, 3, 1, w.short(&[6])),
+            (0x8769, 4, 1, w.long(&[200])),
+            (0x8825, 4, 1, w.long(&[300])),
+        ];
+        w.ifd(8, &ifd0, 460);
+        let exif = [
+            (0x9003, 2, 20, b"2024:05:01 12:30:00\0".to_vec()),
+            (0x829A, 5, 1, w.rationals(&[(1, 250)])),
+            (0x9204, 10, 1, w.long(&[(-1i32) as u32, 3])),
+            (0x9000, 7, 4, b"0232".to_vec()),
+        ];
+        w.ifd(200, &exif, 0);
+        let gps = [
+            (0x0001, 2, 2, b"N\0".to_vec()),
+            (0x0002, 5, 3, w.rationals(&[(48, 1), (51, 1), (2940, 100)])),
+            (0x0003, 2, 2, b"W\0".to_vec()),
+            (0x0004, 5, 3, w.rationals(&[(2, 1), (17, 1), (4020, 100)])),
+            (0x0005, 1, 1, vec![1]),
+            (0x0006, 5, 1, w.rationals(&[(10, 1)])),
+        ];
+        w.ifd(300, &gps, 0);
+        let ifd1 = [
+            (0x0201, 4, 1, w.long(&[480])),
+            (0x0202, 4, 1, w.long(&[32])),
+        ];
+        w.ifd(460, &ifd1, 0);
+        w.buf
+    }
+
+    #[test]
+    fn test_parse() {
+        for endian in [Endianness::Little, Endianness::Big] {
+            let app1 = [EXIF_HEADER, &sample(endian)].concat();
+            let exif = parse(&app1).unwrap();
+            assert_eq!(exif.byte_order, endian);
+            assert_eq!(exif.make.as_deref(), Some("Canon"));
+            assert_eq!(exif.model.as_deref(), Some("EOS"));
+            assert_eq!(exif.orientation, Some(6));
+            assert_eq!(
+                exif.date_time_original.as_deref(),
+                Some("2024:05:01 12:30:00")
+            );
+            assert_eq!(
+                exif.get(Ifd::Exif, 0x829A),
+                Some(&Value::Rational(vec![(1, 250)]))
+            );
+            assert_eq!(
+                exif.get(Ifd::Exif, 0x9204),
+                Some(&Value::SRational(vec![(-1, 3)]))
+            );
+            assert_eq!(
+                exif.get(Ifd::Exif, 0x9000),
+                Some(&Value::Undefined(b"0232".to_vec()))
+            );
+            assert_eq!(exif.get(Ifd::Ifd1, 0x0202), Some(&Value::Long(vec![32])));
+
+            let gps = exif.gps.unwrap();
+            assert!((gps.latitude - 48.858166).abs() < 1e-5, "{:?}", gps);
+            assert!((gps.longitude + 2.294500).abs() < 1e-5, "{:?}", gps);
+            assert_eq!(gps.altitude, Some(-10.0));
+
+            let entry = exif.entries.iter().find(|e| e.tag == 0x0002).unwrap();
+            assert_eq!(entry.name(), Some("GPSLatitude"));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse(b"http://ns.adobe.com/xap/1.0/\0"),
+            Err(ExifError::NotExif)
+        );
+        assert_eq!(
+            parse_tiff(b"XX\0\x2a\0\0\0\x08"),
+            Err(ExifError::InvalidByteOrder([b'X', b'X']))
+        );
+        assert_eq!(
+            parse_tiff(b"II\x2b\0\x08\0\0\0"),
+            Err(ExifError::InvalidMagic(43))
+        );
+        // truncated header
+        assert!(matches!(
+            parse_tiff(b"MM\0\x2a"),
+            Err(ExifError::InvalidOffset { .. })
+        ));
+
+        let mut w = TiffWriter::new(Endianness::Big);
+        // IFD0 offset out of bounds
+        let header = w.long(&[10_000]);
+        w.buf[4..8].copy_from_slice(&header);
+        assert!(matches!(
+            parse_tiff(&w.buf),
+            Err(ExifError::InvalidOffset { offset: 10_000, .. })
+        ));
+
+        // value offset out of bounds & huge count
+        let mut w = TiffWriter::new(Endianness::Little);
+        w.ifd(8, &[(0x010F, 2, 6, b"Canon\0".to_vec())], 0);
+        let bad_offset = w.long(&[u32::MAX]);
+        w.buf[18..22].copy_from_slice(&bad_offset);
+        assert!(matches!(
+            parse_tiff(&w.buf),
+            Err(ExifError::InvalidOffset { .. })
+        ));
+        let mut w = TiffWriter::new(Endianness::Little);
+        w.ifd(8, &[(0x0111, 4, u32::MAX, w.long(&[0, 0]))], 0);
+        assert!(matches!(
+            parse_tiff(&w.buf),
+            Err(ExifError::InvalidOffset { .. })
+        ));
+
+        // IFD1 is IFD0
+        let mut w = TiffWriter::new(Endianness::Little);
+        w.ifd(8, &[(0x0112, 3, 1, w.short(&[1]))], 8);
+        assert_eq!(parse_tiff(&w.buf), Err(ExifError::IfdLoop(8)));
+
+        // pointer with a wrong type
+        let mut w = TiffWriter::new(Endianness::Little);
+        w.ifd(8, &[(0x8769, 2, 4, b"abc\0".to_vec())], 0);
+        assert_eq!(parse_tiff(&w.buf), Err(ExifError::InvalidPointer(0x8769)));
+    }
+}
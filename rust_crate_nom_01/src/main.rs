@@ -5,6 +5,8 @@ use std::env;
 use std::io::Read;
 use std::process;
 
+mod exif;
+
 use nom::bytes::complete::{take, take_while};
 use nom::combinator::map;
 use nom::error::{ErrorKind, ParseError};
@@ -93,6 +95,42 @@ fn main() {
     println!("Reading all...");
     if let Ok((_content, jpeg_segments)) = read_segments(&content[..]) {
         println!("jpeg_segments: {:?}", jpeg_segments);
+
+        // Step 3: Exif metadata (APP1)
+        for segment in jpeg_segments.iter() {
+            if let JpegSegment::AppN(app) = segment {
+                if app.n == 1 && app.identifier == "Exif" {
+                    print_exif(&app.payload);
+                }
+            }
+        }
+    }
+}
+
+fn print_exif(app1: &[u8]) {
+    let exif = match exif::parse(app1) {
+        Ok(exif) => exif,
+        Err(e) => {
+            println!("Invalid exif data: {}", e);
+            return;
+        }
+    };
+    println!("exif byte order: {:?}", exif.byte_order);
+    println!("make: {:?}, model: {:?}", exif.make, exif.model);
+    println!("orientation: {:?}", exif.orientation);
+    println!(
+        "date time: {:?}, original: {:?}, digitized: {:?}",
+        exif.date_time, exif.date_time_original, exif.date_time_digitized
+    );
+    println!("gps: {:?}", exif.gps);
+    for entry in exif.entries.iter() {
+        println!(
+            "  [{:?}] {:#06x} {}: {}",
+            entry.ifd,
+            entry.tag,
+            entry.name().unwrap_or("?"),
+            entry.value
+        );
     }
 }
 
@@ -147,7 +185,6 @@ fn take_soi_2(content: &[u8]) -> IResult<&[u8], &[u8], SoiError<&[u8]>> {
 
 #[derive(Debug, PartialEq)]
 pub enum JpegParseSegmentsError<I> {
-    InvalidApp0Identifier,
     InvalidSegmentSize(u16, u16), // expected n bytes, got n bytes
     UnhandledSegment((u8, u8)),
     Nom(I, ErrorKind),
@@ -165,6 +202,10 @@ impl<I> ParseError<I> for JpegParseSegmentsError<I> {
 
 const JPEG_IDENTIFIER: &[u8] = "JFIF\0".as_bytes();
 const JPEG_APP0_SEGMENT_SIZE: usize = 14; // Without thumbnail data
+const FRAME_HEADER_SIZE: usize = 6; // Without components
+
+// Note: a progressive jpeg has a DHT + SOS per scan (often 10+ scans)
+const MAX_SEGMENTS: usize = 256;
 
 #[derive(Debug)]
 enum DensityUnit {
@@ -200,6 +241,27 @@ impl App0 {
     }
 }
 
+/// Application segment (APP0 - APP15): an identifier (e.g. "Exif", "ICC_PROFILE", "Adobe")
+/// followed by the application data
+#[allow(dead_code)]
+struct AppSegment {
+    n: u8,
+    identifier: String,
+    /// The segment data (starting with the identifier)
+    payload: Vec<u8>,
+}
+
+impl std::fmt::Debug for AppSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // payload can be large (e.g. a thumbnail): only its size
+        f.debug_struct("AppSegment")
+            .field("n", &self.n)
+            .field("identifier", &self.identifier)
+            .field("payload_size", &self.payload.len())
+            .finish()
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 struct Frame {
@@ -213,12 +275,21 @@ struct Frame {
 #[derive(Debug)]
 enum JpegSegment {
     StartOfImage,
-    App(App0), // Application data
+    App(App0),        // Application data (JFIF)
+    AppN(AppSegment), // Application data (any other APPn)
+    // SOFn: 0 baseline, 1 extended, 2 progressive, 3 lossless...
     StartOfFrame(u8, Frame),
     Com(String), // Comment
     Dqt,         // Define Quantization Table
     Dht,         // Define Huffman Table
-    StartOfScan,
+    Dac,         // Define Arithmetic Coding conditioning
+    Dri(u16),    // Define Restart Interval (in MCUs)
+    Dnl(u16),    // Define Number of Lines
+    // restart_markers: RSTn markers found in the entropy coded data
+    StartOfScan { restart_markers: usize },
+    Restart(u8),    // RSTn (outside of a scan)
+    Tem,            // Temporary (arithmetic coding)
+    Other(u8, u16), // Any other segment (marker & size), skipped
     EndOfImage,
 }
 
@@ -232,160 +303,211 @@ fn take_segment_size(content: &[u8]) -> IResult<&[u8], u16, JpegParseSegmentsErr
     be_u16(content)
 }
 
-fn take_segment_data(
-    segment_id: (u8, u8),
-    content: &[u8],
-) -> IResult<&[u8], JpegSegment, JpegParseSegmentsError<&[u8]>> {
-    let (content, segment) = match segment_id {
-        (0xFF, 0xD8) => {
-            // Start of Image
-            (content, JpegSegment::StartOfImage)
-        }
-        (0xFF, 0xE0) => {
-            // App0
-
-            // Note: segment_size is the size of the current_segment including the length
-            let (bytes_after, segment_size_) = take_segment_size(content)?;
+fn take_segment_payload(content: &[u8]) -> IResult<&[u8], &[u8], JpegParseSegmentsError<&[u8]>> {
+    // Read a segment size then the segment data
+    // Note: segment_size is the size of the current_segment including the length (2 bytes)
+    let (bytes_after, segment_size) = take_segment_size(content)?;
+    if segment_size < 2 {
+        return Err(nom::Err::Error(JpegParseSegmentsError::InvalidSegmentSize(
+            segment_size,
+            2,
+        )));
+    }
+    take(segment_size - 2)(bytes_after)
+}
 
-            let segment_size = usize::from(segment_size_);
-            if segment_size < JPEG_APP0_SEGMENT_SIZE {
-                return Err(nom::Err::Error(JpegParseSegmentsError::InvalidSegmentSize(
-                    segment_size_,
-                    u16::try_from(JPEG_APP0_SEGMENT_SIZE).unwrap(), // safe as it's a const value (could static assert)
-                )));
-            }
+fn take_segment_u16(content: &[u8]) -> IResult<&[u8], u16, JpegParseSegmentsError<&[u8]>> {
+    // A segment with a single u16 value (DRI, DNL)
+    let (bytes_after, segment_size) = take_segment_size(content)?;
+    if segment_size != 4 {
+        return Err(nom::Err::Error(JpegParseSegmentsError::InvalidSegmentSize(
+            segment_size,
+            4,
+        )));
+    }
+    be_u16(bytes_after)
+}
 
-            let (bytes_after_app0, app0_content) = take(JPEG_APP0_SEGMENT_SIZE)(bytes_after)?;
+fn parse_app0(payload: &[u8]) -> Option<App0> {
+    // JFIF APP0 (None for any other APP0, e.g. JFXX)
+    if payload.len() < JPEG_APP0_SEGMENT_SIZE || &payload[..5] != JPEG_IDENTIFIER {
+        return None;
+    }
+    let mut file_identifier_mark = [0u8; 5];
+    file_identifier_mark.copy_from_slice(&payload[..5]);
+    // Note: thumbnail data (thumbnail_width * thumbnail_height * 3 bytes) is discarded
+    Some(App0 {
+        file_identifier_mark,
+        major_revision_number: payload[5],
+        minor_revision_number: payload[6],
+        units_for_density: payload[7],
+        x_density: u16::from_be_bytes([payload[8], payload[9]]),
+        y_density: u16::from_be_bytes([payload[10], payload[11]]),
+        thumbnail_width: payload[12],
+        thumbnail_height: payload[13],
+    })
+}
 
-            let file_identifier_mark = [
-                app0_content[0],
-                app0_content[1],
-                app0_content[2],
-                app0_content[3],
-                app0_content[4],
-            ];
+fn parse_app_segment(n: u8, payload: &[u8]) -> AppSegment {
+    // The identifier is a null terminated string (if any)
+    let identifier = match payload.iter().position(|b| *b == 0) {
+        Some(end) => &payload[..end],
+        None => &payload[..0],
+    };
+    AppSegment {
+        n,
+        identifier: String::from_utf8_lossy(identifier).to_string(),
+        payload: payload.to_vec(),
+    }
+}
 
-            if file_identifier_mark != JPEG_IDENTIFIER {
-                return Err(nom::Err::Error(
-                    JpegParseSegmentsError::InvalidApp0Identifier,
-                ));
+fn take_scan_data(content: &[u8]) -> IResult<&[u8], usize, JpegParseSegmentsError<&[u8]>> {
+    // Entropy coded data: until a marker that is not a RSTn (e.g. EOI, DHT, SOS)
+    let mut ba = content;
+    let mut restart_markers = 0;
+    loop {
+        // Read until we found a byte == OxFF
+        let (ba1, _br) = take_while(|i| i != 0xFF)(ba)?;
+        // Read the next 2 bytes (0xFF, 0x??)
+        let (ba2, br2) = take(2usize)(ba1)?;
+
+        match br2[1] {
+            // Skip byte stuffing
+            0x00 => ba = ba2,
+            // RSTn: part of the scan
+            0xD0..=0xD7 => {
+                restart_markers += 1;
+                ba = ba2
             }
-
-            let thumbnail_width = app0_content[12];
-            let thumbnail_height = app0_content[13];
-            let thumbnail_data_size: usize =
-                usize::from(thumbnail_width) * usize::from(thumbnail_height) * 3;
-
-            let app0 = App0 {
-                file_identifier_mark,
-                major_revision_number: app0_content[5],
-                minor_revision_number: app0_content[6],
-                units_for_density: app0_content[7],
-                x_density: u16::from_be_bytes([app0_content[8], app0_content[9]]),
-                y_density: u16::from_be_bytes([app0_content[10], app0_content[11]]),
-                thumbnail_width,
-                thumbnail_height,
-            };
-
-            // Discard thumbnail data
-            let (bytes_after_thumbnail, _thumbnail_data) =
-                take(thumbnail_data_size)(bytes_after_app0)?;
-
-            // APP0
-            (bytes_after_thumbnail, JpegSegment::App(app0))
+            _ => return Ok((ba1, restart_markers)),
         }
-        (0xFF, 0xC0) => {
-            // Start Of Frame (baseline DCT)
+    }
+}
 
-            let (bytes_after, _segment_size) = take_segment_size(content)?;
-            // XXX: could check against segment size as well
-            let (bytes_after_frame0, frame_0_content) = take(8usize)(bytes_after)?;
+fn take_segment_data(
+    segment_id: (u8, u8),
+    content: &[u8],
+) -> IResult<&[u8], JpegSegment, JpegParseSegmentsError<&[u8]>> {
+    if segment_id.0 != 0xFF {
+        // not a marker
+        return Err(nom::Err::Error(JpegParseSegmentsError::UnhandledSegment(
+            segment_id,
+        )));
+    }
 
-            let frame = Frame {
-                data_precision: frame_0_content[0],
-                image_height: u16::from_be_bytes([frame_0_content[1], frame_0_content[2]]),
-                image_width: u16::from_be_bytes([frame_0_content[3], frame_0_content[4]]),
-                components: frame_0_content[5],
+    let (content, segment) = match segment_id.1 {
+        // Start of Image
+        0xD8 => (content, JpegSegment::StartOfImage),
+        0xD9 => (content, JpegSegment::EndOfImage),
+        // Segments without size
+        0xD0..=0xD7 => (content, JpegSegment::Restart(segment_id.1 - 0xD0)),
+        0x01 => (content, JpegSegment::Tem),
+        0xE0..=0xEF => {
+            // APPn
+            let n = segment_id.1 - 0xE0;
+            let (bytes_after, payload) = take_segment_payload(content)?;
+            let segment = match parse_app0(payload) {
+                Some(app0) if n == 0 => JpegSegment::App(app0),
+                _ => JpegSegment::AppN(parse_app_segment(n, payload)),
             };
-
-            (bytes_after_frame0, JpegSegment::StartOfFrame(0, frame))
+            (bytes_after, segment)
         }
-        (0xFF, 0xC2) => {
-            // Start Of Frame (progressive DCT)
-            let (bytes_after, segment_size) = take_segment_size(content)?;
-            let (bytes_after_frame2, frame_0_content) = take(segment_size - 2)(bytes_after)?;
+        0xC0..=0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+            // Start Of Frame: SOF0 (baseline DCT), SOF1 (extended sequential DCT),
+            // SOF2 (progressive DCT), SOF3 (lossless), SOF5-7 (differential, huffman),
+            // SOF9-11 & SOF13-15 (arithmetic coding)
+            let (bytes_after_frame, frame_content) = take_segment_payload(content)?;
+            if frame_content.len() < FRAME_HEADER_SIZE {
+                return Err(nom::Err::Error(JpegParseSegmentsError::InvalidSegmentSize(
+                    frame_content.len() as u16 + 2,
+                    FRAME_HEADER_SIZE as u16 + 2,
+                )));
+            }
 
             let frame = Frame {
-                data_precision: frame_0_content[0],
-                image_height: u16::from_be_bytes([frame_0_content[1], frame_0_content[2]]),
-                image_width: u16::from_be_bytes([frame_0_content[3], frame_0_content[4]]),
-                components: frame_0_content[5],
+                data_precision: frame_content[0],
+                image_height: u16::from_be_bytes([frame_content[1], frame_content[2]]),
+                image_width: u16::from_be_bytes([frame_content[3], frame_content[4]]),
+                components: frame_content[5],
             };
 
-            (bytes_after_frame2, JpegSegment::StartOfFrame(2, frame))
+            (
+                bytes_after_frame,
+                JpegSegment::StartOfFrame(segment_id.1 - 0xC0, frame),
+            )
         }
-        (0xFF, 0xD9) => (content, JpegSegment::EndOfImage),
-        (0xFF, 0xFE) => {
+        0xFE => {
             // COM
-            let (bytes_after, segment_size) = take_segment_size(content)?;
-            let (bytes_after_comment, comment) = take(segment_size - 2)(bytes_after)?;
+            let (bytes_after_comment, comment) = take_segment_payload(content)?;
             // Note: as segment size is u16, the comment size is bounded as well
             (
                 bytes_after_comment,
                 JpegSegment::Com(String::from_utf8_lossy(comment).to_string()),
             )
         }
-        (0xFF, 0xC4) => {
+        0xC4 => {
             // DHT
-            let (bytes_after, segment_size) = take_segment_size(content)?;
-            let (bytes_after_dht, _dht_data) = take(segment_size - 2)(bytes_after)?;
+            let (bytes_after_dht, _dht_data) = take_segment_payload(content)?;
             (bytes_after_dht, JpegSegment::Dht)
         }
-        (0xFF, 0xDB) => {
+        0xCC => {
+            // DAC
+            let (bytes_after_dac, _dac_data) = take_segment_payload(content)?;
+            (bytes_after_dac, JpegSegment::Dac)
+        }
+        0xDB => {
             // DQT
-            let (bytes_after, segment_size) = take_segment_size(content)?;
-            let (bytes_after_dqt, _dqt_data) = take(segment_size - 2)(bytes_after)?;
+            let (bytes_after_dqt, _dqt_data) = take_segment_payload(content)?;
             (bytes_after_dqt, JpegSegment::Dqt)
         }
-        (0xFF, 0xDA) => {
+        0xDD => {
+            // DRI
+            let (bytes_after_dri, restart_interval) = take_segment_u16(content)?;
+            (bytes_after_dri, JpegSegment::Dri(restart_interval))
+        }
+        0xDC => {
+            // DNL
+            let (bytes_after_dnl, lines) = take_segment_u16(content)?;
+            (bytes_after_dnl, JpegSegment::Dnl(lines))
+        }
+        0xDA => {
             // SOS
-            let (bytes_after, segment_size) = take_segment_size(content)?;
-            let (bytes_after_sos_header, _sos_header) = take(segment_size - 2)(bytes_after)?;
+            let (bytes_after_sos_header, _sos_header) = take_segment_payload(content)?;
             // let components_in_scan = sos_header_[0];
-
-            // FIXME / TODO: keep track of bytes count read? check <= segment_size
-            let mut ba = bytes_after_sos_header;
-            loop {
-                // Read until we found a byte == OxFF
-                let (ba1, _br) = take_while(|i| i != 0xFF)(ba)?;
-                // Read the next 2 bytes (0xFF, 0x??)
-                let (ba2, br2) = take(2usize)(ba1)?;
-
-                if br2[0] == 0xFF && br2[1] == 0x00 {
-                    // Skip byte stuffing
-                    ba = ba2
-                } else {
-                    ba = ba1;
-                    break;
-                }
-            }
-
-            (ba, JpegSegment::StartOfScan)
+            let (ba, restart_markers) = take_scan_data(bytes_after_sos_header)?;
+            (ba, JpegSegment::StartOfScan { restart_markers })
+        }
+        0x00 | 0xFF => {
+            // stuffed byte or fill byte: not a marker
+            return Err(nom::Err::Error(JpegParseSegmentsError::UnhandledSegment(
+                segment_id,
+            )));
         }
-        _ => {
-            return Err(nom::Err::Error(JpegParseSegmentsError::UnhandledSegment((
-                segment_id.0,
-                segment_id.1,
-            ))));
+        marker => {
+            // Any other marker (JPGn, DHP, EXP, reserved...): skipped, using the segment size
+            let (bytes_after, payload) = take_segment_payload(content)?;
+            (
+                bytes_after,
+                JpegSegment::Other(marker, payload.len() as u16 + 2),
+            )
         }
     };
 
     Ok((content, segment))
 }
 
+fn skip_fill_bytes(content: &[u8]) -> &[u8] {
+    // A marker can be preceded by any number of fill bytes (0xFF)
+    let mut content = content;
+    while content.len() > 2 && content[0] == 0xFF && content[1] == 0xFF {
+        content = &content[1..];
+    }
+    content
+}
+
 fn read_segment(content: &[u8]) -> IResult<&[u8], JpegSegment, JpegParseSegmentsError<&[u8]>> {
     // Read one JPEG segment
-    take_segment_id_2(content).and_then(|(content, segment_id)| {
+    take_segment_id_2(skip_fill_bytes(content)).and_then(|(content, segment_id)| {
         take_segment_data((segment_id[0], segment_id[1]), content)
     })
 }
@@ -395,12 +517,91 @@ fn read_segments(
 ) -> IResult<&[u8], Vec<JpegSegment>, JpegParseSegmentsError<&[u8]>> {
     // Note: should compute max "number of segment to read" according to file size (with a max file size allowed)
     //       as segment_size is u16
-    // Note 2: not all segment types are supported, but it can read this jpeg image:
+    // Note 2: unknown segments are skipped (using their size), e.g. it can read this jpeg image:
     //         https://upload.wikimedia.org/wikipedia/commons/3/3f/JPEG_example_flower.jpg
-    many_m_n(0, 32, read_segment).parse(content)
+    //         and jpeg images from cameras (Exif in APP1)
+    many_m_n(0, MAX_SEGMENTS, read_segment).parse(content)
     // Note: based on the many_m_n result, we could do some additional checks like:
     // * start with SOI, end with SOI
     // * not too many COM?
     // * has the required segments (APP0, DQT...)
     // * check that the bytes after SOI are empty
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_segments() {
+        #[rustfmt::skip]
+        let content: &[u8] = &[
+            0xFF, 0xD8, // SOI
+            0xFF, 0xE1, 0x00, 0x0A, b'E', b'x', b'i', b'f', 0x00, 0x00, 0x4D, 0x4D, // APP1
+            0xFF, 0xDD, 0x00, 0x04, 0x00, 0x10, // DRI
+            0xFF, 0xCC, 0x00, 0x04, 0x01, 0x02, // DAC
+            0xFF, 0xC1, 0x00, 0x0B, 0x08, 0x00, 0x00, 0x00, 0x20, 0x01, 0x01, 0x11, 0x00, // SOF1
+            0xFF, 0xF0, 0x00, 0x03, 0x00, // JPG0: unknown, skipped
+            0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00, // SOS
+            0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD0, 0x56, 0xFF, 0xD1, 0x78, // scan data
+            0xFF, 0xFF, 0xDC, 0x00, 0x04, 0x00, 0x10, // fill byte & DNL
+            0xFF, 0xD9, // EOI
+        ];
+        let (rest, segments) = read_segments(content).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(segments.len(), 9);
+        match &segments[1] {
+            JpegSegment::AppN(app) => {
+                assert_eq!((app.n, app.identifier.as_str()), (1, "Exif"));
+                assert_eq!(app.payload, b"Exif\0\0MM");
+            }
+            s => panic!("unexpected segment: {:?}", s),
+        }
+        assert!(matches!(segments[2], JpegSegment::Dri(16)));
+        assert!(matches!(segments[3], JpegSegment::Dac));
+        assert!(matches!(
+            segments[4],
+            JpegSegment::StartOfFrame(
+                1,
+                Frame {
+                    image_height: 0,
+                    image_width: 32,
+                    ..
+                }
+            )
+        ));
+        assert!(matches!(segments[5], JpegSegment::Other(0xF0, 3)));
+        assert!(matches!(
+            segments[6],
+            JpegSegment::StartOfScan { restart_markers: 2 }
+        ));
+        assert!(matches!(segments[7], JpegSegment::Dnl(16)));
+        assert!(matches!(segments[8], JpegSegment::EndOfImage));
+    }
+
+    #[test]
+    fn test_invalid_segment_size() {
+        let res = read_segment(&[0xFF, 0xE1, 0x00, 0x01]);
+        assert_eq!(
+            res.err(),
+            Some(nom::Err::Error(JpegParseSegmentsError::InvalidSegmentSize(
+                1, 2
+            )))
+        );
+        let res = read_segment(&[0xFF, 0xDD, 0x00, 0x05, 0x00, 0x10, 0x00]);
+        assert_eq!(
+            res.err(),
+            Some(nom::Err::Error(JpegParseSegmentsError::InvalidSegmentSize(
+                5, 4
+            )))
+        );
+        // not a marker
+        let res = read_segment(&[0x12, 0x34]);
+        assert_eq!(
+            res.err(),
+            Some(nom::Err::Error(JpegParseSegmentsError::UnhandledSegment((
+                0x12, 0x34
+            ))))
+        );
+    }
+}
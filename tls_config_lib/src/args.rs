@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+
+/// Split the command line arguments into positional arguments and `--name value` options
+///
+/// Note: an option without value (e.g. --identity-headers) is either the last argument or
+///       followed by another option, its value is an empty string
+pub fn parse_args() -> (Vec<String>, HashMap<String, String>) {
+    split_args(std::env::args().skip(1))
+}
+
+/// See [parse_args]
+pub fn split_args<I>(args: I) -> (Vec<String>, HashMap<String, String>)
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut positional = vec![];
+    let mut options = HashMap::new();
+    let mut args = args.into_iter().peekable();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("--") {
+            Some(name) => {
+                let value = args.next_if(|a| !a.starts_with("--")).unwrap_or_default();
+                options.insert(name.to_string(), value);
+            }
+            None => positional.push(arg),
+        }
+    }
+    (positional, options)
+}
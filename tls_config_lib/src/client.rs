@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustls::crypto::aws_lc_rs as provider;
+use rustls::{ClientConfig, RootCertStore, SupportedProtocolVersion};
+use rustls_pki_types::CertificateDer;
+use tokio_rustls::TlsConnector;
+
+use crate::{load_certs, load_private_key, PinnedCertificateVerification, TlsConfigError};
+
+/// Build a rustls ClientConfig (or a TlsConnector)
+///
+/// The server certificate is verified against the root CA(s) or, if a fingerprint is pinned,
+/// only its sha256 fingerprint is checked
+///
+/// Files are only read in build(), so all the errors are reported there
+#[derive(Debug, Default)]
+pub struct TlsClientBuilder {
+    root_ca_files: Vec<PathBuf>,
+    root_certs: Vec<CertificateDer<'static>>,
+    client_cert: Option<(PathBuf, PathBuf)>,
+    pin: Option<Vec<u8>>,
+    alpn: Vec<Vec<u8>>,
+    versions: Option<Vec<&'static SupportedProtocolVersion>>,
+}
+
+impl TlsClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust the certificates of a pem file (can be called multiple times)
+    pub fn root_ca<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.root_ca_files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Trust a certificate already loaded (or generated)
+    pub fn root_cert(mut self, cert: CertificateDer<'static>) -> Self {
+        self.root_certs.push(cert);
+        self
+    }
+
+    /// Client certificate & private key (pem files), sent if the server requests client auth
+    pub fn client_cert<C: AsRef<Path>, K: AsRef<Path>>(mut self, cert: C, key: K) -> Self {
+        self.client_cert = Some((cert.as_ref().to_path_buf(), key.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Only accept a server certificate with this sha256 fingerprint (see parse_fingerprint)
+    /// WARNING: the certificate chain, the server name and the validity dates are NOT verified
+    pub fn pin(mut self, fingerprint: Vec<u8>) -> Self {
+        self.pin = Some(fingerprint);
+        self
+    }
+
+    /// Application protocols to offer, by order of preference (e.g. h2, http/1.1)
+    pub fn alpn<S: AsRef<str>>(mut self, protocols: &[S]) -> Self {
+        self.alpn = protocols
+            .iter()
+            .map(|p| p.as_ref().as_bytes().to_vec())
+            .collect();
+        self
+    }
+
+    /// Protocol versions (default: Tls 1.2 & 1.3)
+    pub fn versions(mut self, versions: &[&'static SupportedProtocolVersion]) -> Self {
+        self.versions = Some(versions.to_vec());
+        self
+    }
+
+    pub fn build(self) -> Result<ClientConfig, TlsConfigError> {
+        let mut root_store = RootCertStore::empty();
+        for path in self.root_ca_files.iter() {
+            for cert in load_certs(path)? {
+                root_store.add(cert)?;
+            }
+        }
+        for cert in self.root_certs {
+            root_store.add(cert)?;
+        }
+        if root_store.is_empty() && self.pin.is_none() {
+            return Err(TlsConfigError::NoTrustAnchor);
+        }
+
+        let versions = self
+            .versions
+            .unwrap_or_else(|| rustls::DEFAULT_VERSIONS.to_vec());
+        let builder = ClientConfig::builder_with_provider(provider::default_provider().into())
+            .with_protocol_versions(&versions)?
+            .with_root_certificates(root_store);
+
+        let mut config = match self.client_cert {
+            Some((cert, key)) => {
+                builder.with_client_auth_cert(load_certs(cert)?, load_private_key(key)?)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = self.alpn;
+
+        if let Some(fingerprint) = self.pin {
+            config.dangerous().set_certificate_verifier(Arc::new(
+                PinnedCertificateVerification::new(provider::default_provider(), fingerprint),
+            ));
+        }
+
+        Ok(config)
+    }
+
+    pub fn build_connector(self) -> Result<TlsConnector, TlsConfigError> {
+        Ok(TlsConnector::from(Arc::new(self.build()?)))
+    }
+}
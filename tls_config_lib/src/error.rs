@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsConfigError {
+    #[error("Unable to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("No certificate found in {0}")]
+    NoCertificate(PathBuf),
+    #[error("No private key found in {0}")]
+    NoPrivateKey(PathBuf),
+    #[error("A root CA or a certificate fingerprint (pinning) is required")]
+    NoTrustAnchor,
+    #[error("Invalid sha256 fingerprint: {0}")]
+    InvalidFingerprint(String),
+    #[error("Unsupported protocol version: {0} (supported: 1.2, 1.3)")]
+    UnsupportedVersion(String),
+    #[error("Invalid client verifier: {0}")]
+    ClientVerifier(#[from] rustls::server::VerifierBuilderError),
+    #[error("Tls error: {0}")]
+    Rustls(#[from] rustls::Error),
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+/// Why a Tls handshake did not complete
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandshakeFailure {
+    Timeout,
+    InvalidMessage,
+    AlertReceived,
+    InvalidCertificate,
+    NoCertificatesPresented,
+    PeerIncompatible,
+    PeerMisbehaved,
+    Tls,
+    Io,
+}
+
+impl HandshakeFailure {
+    pub fn from_io_error(e: &std::io::Error) -> Self {
+        // tokio-rustls wraps rustls errors in an io::Error (kind: InvalidData)
+        match e.get_ref().and_then(|e| e.downcast_ref::<rustls::Error>()) {
+            Some(rustls::Error::InvalidMessage(_)) => HandshakeFailure::InvalidMessage,
+            Some(rustls::Error::AlertReceived(_)) => HandshakeFailure::AlertReceived,
+            Some(rustls::Error::InvalidCertificate(_)) => HandshakeFailure::InvalidCertificate,
+            Some(rustls::Error::NoCertificatesPresented) => {
+                HandshakeFailure::NoCertificatesPresented
+            }
+            Some(rustls::Error::PeerIncompatible(_)) => HandshakeFailure::PeerIncompatible,
+            Some(rustls::Error::PeerMisbehaved(_)) => HandshakeFailure::PeerMisbehaved,
+            Some(_) => HandshakeFailure::Tls,
+            None => HandshakeFailure::Io,
+        }
+    }
+}
+
+/// Handshake failure counters, shared between all connection tasks
+#[derive(Debug, Default)]
+pub struct HandshakeStats {
+    failures: Mutex<HashMap<HandshakeFailure, u64>>,
+}
+
+impl HandshakeStats {
+    /// Record a failure and return the number of failures seen so far for this reason
+    pub fn record_failure(&self, reason: HandshakeFailure) -> u64 {
+        let mut failures = self.failures.lock().unwrap();
+        let count = failures.entry(reason).or_insert(0);
+        *count += 1;
+        *count
+    }
+}
+
+/// Tls handshake of an accepted connection, within `timeout`
+///
+/// The result is logged (negotiated parameters or failure reason), failures are counted in `stats`
+///
+/// Note: run it in the connection task so a slow (or malicious) client cannot block the accept
+///       loop
+pub async fn handshake<IO>(
+    acceptor: &TlsAcceptor,
+    socket: IO,
+    peer_addr: SocketAddr,
+    timeout: Duration,
+    stats: &HandshakeStats,
+) -> Option<TlsStream<IO>>
+where
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    let start = Instant::now();
+    let stream = match tokio::time::timeout(timeout, acceptor.accept(socket)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            let reason = HandshakeFailure::from_io_error(&e);
+            let count = stats.record_failure(reason);
+            println!(
+                "[Tls] handshake with {} failed ({:?}, total: {}): {}",
+                peer_addr, reason, count, e
+            );
+            return None;
+        }
+        Err(_) => {
+            let count = stats.record_failure(HandshakeFailure::Timeout);
+            println!(
+                "[Tls] handshake with {} failed ({:?}, total: {}): no handshake after {:?}",
+                peer_addr,
+                HandshakeFailure::Timeout,
+                count,
+                timeout
+            );
+            return None;
+        }
+    };
+
+    let (_, conn) = stream.get_ref();
+    println!(
+        "[Tls] handshake with {} done in {:?} - version: {:?}, cipher suite: {:?}, alpn: {:?}, kind: {:?}, client certs: {}",
+        peer_addr,
+        start.elapsed(),
+        conn.protocol_version(),
+        conn.negotiated_cipher_suite().map(|s| s.suite()),
+        conn.alpn_protocol().map(String::from_utf8_lossy),
+        conn.handshake_kind(),
+        conn.peer_certificates().map(|c| c.len()).unwrap_or(0),
+    );
+    Some(stream)
+}
@@ -0,0 +1,165 @@
+//! Tls configuration shared by the network examples (tokio_tcp_echo, tokio_tcp_tls, hyper_01_http_post)
+//!
+//! * TlsServerBuilder: server certificate + key, client auth (mTLS), alpn, protocol versions
+//! * TlsClientBuilder: trust roots, client certificate, certificate pinning, alpn, protocol versions
+//! * handshake: server handshake with a timeout, logged and failures counted (HandshakeStats)
+//! * parse_args: command line arguments shared by the examples (positional + `--name value`)
+//!
+//! Example:
+//!
+//! let acceptor = TlsServerBuilder::from_pem_files("cert.pem", "key.pem")
+//!     .client_auth_required("root_ca.pem")
+//!     .alpn(&["h2", "http/1.1"])
+//!     .build_acceptor()?;
+//!
+//! let connector = TlsClientBuilder::new()
+//!     .root_ca("root_ca.pem")
+//!     .client_cert("client1.crt", "client1.key")
+//!     .build_connector()?;
+
+mod args;
+mod client;
+mod error;
+mod handshake;
+mod pem;
+mod server;
+mod verifier;
+
+pub use args::{parse_args, split_args};
+pub use client::TlsClientBuilder;
+pub use error::TlsConfigError;
+pub use handshake::{handshake, HandshakeFailure, HandshakeStats};
+pub use pem::{
+    fingerprint, load_certs, load_private_key, load_root_store, parse_fingerprint, to_hex,
+    to_hex_with,
+};
+pub use server::{ClientAuth, TlsServerBuilder};
+pub use verifier::PinnedCertificateVerification;
+
+use rustls::SupportedProtocolVersion;
+
+/// Parse a protocol version: "1.2" or "1.3" (a "tls" prefix is accepted: "tls1.3")
+pub fn parse_version(s: &str) -> Result<&'static SupportedProtocolVersion, TlsConfigError> {
+    let version = s.trim().to_ascii_lowercase();
+    match version.strip_prefix("tls").unwrap_or(&version) {
+        "1.2" | "12" => Ok(&rustls::version::TLS12),
+        "1.3" | "13" => Ok(&rustls::version::TLS13),
+        _ => Err(TlsConfigError::UnsupportedVersion(s.to_string())),
+    }
+}
+
+/// Parse a comma separated list of protocol versions: "1.2,1.3"
+pub fn parse_versions(s: &str) -> Result<Vec<&'static SupportedProtocolVersion>, TlsConfigError> {
+    s.split(',').map(parse_version).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Write a self signed certificate (+ key) for `name` in a temp dir
+    fn self_signed(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("tls_config_lib_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let certified = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+        let cert_path = dir.join(format!("{}.crt", name));
+        let key_path = dir.join(format!("{}.key", name));
+        std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+        std::fs::write(&key_path, certified.signing_key.serialize_pem()).unwrap();
+        (cert_path, key_path)
+    }
+
+    #[test]
+    fn test_parse_versions() {
+        let versions = parse_versions("1.2,TLS1.3").unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].version, rustls::ProtocolVersion::TLSv1_2);
+        assert_eq!(versions[1].version, rustls::ProtocolVersion::TLSv1_3);
+        assert!(matches!(
+            parse_version("1.1"),
+            Err(TlsConfigError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn test_split_args() {
+        let args = [
+            "127.0.0.1:7171",
+            "--identity-headers",
+            "--client-ca",
+            "ca.pem",
+            "b",
+            "--h2c",
+        ];
+        let (positional, options) = split_args(args.iter().map(|a| a.to_string()));
+        assert_eq!(positional, vec!["127.0.0.1:7171", "b"]);
+        assert_eq!(options.len(), 3);
+        assert_eq!(options["identity-headers"], "");
+        assert_eq!(options["client-ca"], "ca.pem");
+        assert_eq!(options["h2c"], "");
+    }
+
+    #[test]
+    fn test_parse_fingerprint() {
+        let hex = format!("3F:a2:{}", ["00"; 30].join(":"));
+        let fp = parse_fingerprint(&hex).unwrap();
+        assert_eq!(fp.len(), 32);
+        assert_eq!(&fp[..3], &[0x3f, 0xa2, 0x00]);
+        assert_eq!(to_hex(&fp), hex.to_ascii_lowercase());
+        assert_eq!(to_hex_with(&fp[..3], ""), "3fa200");
+        assert!(matches!(
+            parse_fingerprint("3f:a2"),
+            Err(TlsConfigError::InvalidFingerprint(_))
+        ));
+        assert!(matches!(
+            parse_fingerprint(&"zz".repeat(32)),
+            Err(TlsConfigError::InvalidFingerprint(_))
+        ));
+    }
+
+    #[test]
+    fn test_missing_files() {
+        let err = TlsServerBuilder::from_pem_files("/nonexistent/cert.pem", "/nonexistent/key.pem")
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, TlsConfigError::Io { .. }));
+
+        // No root CA & no pin
+        let err = TlsClientBuilder::new().build().unwrap_err();
+        assert!(matches!(err, TlsConfigError::NoTrustAnchor));
+    }
+
+    #[test]
+    fn test_server_client_config() {
+        let (cert, key) = self_signed("localhost");
+
+        let config = TlsServerBuilder::from_pem_files(&cert, &key)
+            .client_auth_required(&cert)
+            .alpn(&["h2", "http/1.1"])
+            .versions(&[&rustls::version::TLS13])
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.alpn_protocols,
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        );
+
+        // A certificate file is not a key
+        let err = TlsServerBuilder::from_pem_files(&cert, &cert)
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, TlsConfigError::NoPrivateKey(_)));
+
+        let config = TlsClientBuilder::new()
+            .root_ca(&cert)
+            .client_cert(&cert, &key)
+            .alpn(&["h2"])
+            .build()
+            .unwrap();
+        assert!(config.client_auth_cert_resolver.has_certs());
+        assert_eq!(config.alpn_protocols, vec![b"h2".to_vec()]);
+
+        std::fs::remove_dir_all(cert.parent().unwrap()).unwrap();
+    }
+}
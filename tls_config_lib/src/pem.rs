@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use rustls::RootCertStore;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use sha2::{Digest, Sha256};
+
+use crate::TlsConfigError;
+
+fn open<P: AsRef<Path>>(path: P) -> Result<BufReader<File>, TlsConfigError> {
+    File::open(path.as_ref())
+        .map(BufReader::new)
+        .map_err(|source| TlsConfigError::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        })
+}
+
+/// Load all the certificates of a pem file (error if there is none)
+pub fn load_certs<P>(path: P) -> Result<Vec<CertificateDer<'static>>, TlsConfigError>
+where
+    P: AsRef<Path>,
+{
+    let certs = rustls_pemfile::certs(&mut open(&path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|source| TlsConfigError::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        })?;
+    if certs.is_empty() {
+        return Err(TlsConfigError::NoCertificate(path.as_ref().to_path_buf()));
+    }
+    Ok(certs)
+}
+
+/// Load the first private key (pkcs1 / pkcs8 / sec1) of a pem file
+pub fn load_private_key<P>(path: P) -> Result<PrivateKeyDer<'static>, TlsConfigError>
+where
+    P: AsRef<Path>,
+{
+    rustls_pemfile::private_key(&mut open(&path)?)
+        .map_err(|source| TlsConfigError::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        })?
+        .ok_or_else(|| TlsConfigError::NoPrivateKey(path.as_ref().to_path_buf()))
+}
+
+/// Load the certificates of a pem file (e.g. root_ca.pem) as trust roots
+pub fn load_root_store<P>(path: P) -> Result<RootCertStore, TlsConfigError>
+where
+    P: AsRef<Path>,
+{
+    let mut root_store = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        root_store.add(cert)?;
+    }
+    Ok(root_store)
+}
+
+/// Lowercase hex, bytes separated by ':' (e.g. 3f:a2:...)
+pub fn to_hex(bytes: &[u8]) -> String {
+    to_hex_with(bytes, ":")
+}
+
+/// Lowercase hex, bytes separated by `separator` (e.g. "" for 3fa2...)
+pub fn to_hex_with(bytes: &[u8], separator: &str) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+/// Certificate sha256 fingerprint (e.g. 3f:a2:...)
+pub fn fingerprint(cert: &CertificateDer<'_>) -> String {
+    to_hex(Sha256::digest(cert.as_ref()).as_slice())
+}
+
+/// Parse a sha256 fingerprint like 3f:a2:... or 3FA2...
+pub fn parse_fingerprint(s: &str) -> Result<Vec<u8>, TlsConfigError> {
+    let hex: String = s.chars().filter(|c| *c != ':').collect();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(TlsConfigError::InvalidFingerprint(s.to_string()));
+    }
+    Ok((0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect())
+}
@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustls::crypto::aws_lc_rs as provider;
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ServerConfig, SupportedProtocolVersion};
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{load_certs, load_private_key, load_root_store, TlsConfigError};
+
+/// Client authentication (mTLS): client certificates must be issued by the given root CA
+#[derive(Debug, Clone, Default)]
+pub enum ClientAuth {
+    #[default]
+    None,
+    /// Clients without a certificate are accepted (but an invalid certificate is refused)
+    Optional(PathBuf),
+    Required(PathBuf),
+}
+
+#[derive(Debug)]
+enum Identity {
+    PemFiles { cert: PathBuf, key: PathBuf },
+    Der(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>),
+}
+
+/// Build a rustls ServerConfig (or a TlsAcceptor)
+///
+/// Files are only read in build(), so all the errors are reported there
+#[derive(Debug)]
+pub struct TlsServerBuilder {
+    identity: Identity,
+    client_auth: ClientAuth,
+    alpn: Vec<Vec<u8>>,
+    versions: Vec<&'static SupportedProtocolVersion>,
+}
+
+impl TlsServerBuilder {
+    /// Server certificate chain (leaf first) & private key from pem files
+    pub fn from_pem_files<C: AsRef<Path>, K: AsRef<Path>>(cert: C, key: K) -> Self {
+        Self::new(Identity::PemFiles {
+            cert: cert.as_ref().to_path_buf(),
+            key: key.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Server certificate chain (leaf first) & private key already loaded (or generated)
+    pub fn from_der(certs: Vec<CertificateDer<'static>>, key: PrivateKeyDer<'static>) -> Self {
+        Self::new(Identity::Der(certs, key))
+    }
+
+    fn new(identity: Identity) -> Self {
+        Self {
+            identity,
+            client_auth: ClientAuth::None,
+            alpn: vec![],
+            versions: rustls::DEFAULT_VERSIONS.to_vec(),
+        }
+    }
+
+    pub fn client_auth(mut self, client_auth: ClientAuth) -> Self {
+        self.client_auth = client_auth;
+        self
+    }
+
+    pub fn client_auth_required<P: AsRef<Path>>(self, root_ca: P) -> Self {
+        self.client_auth(ClientAuth::Required(root_ca.as_ref().to_path_buf()))
+    }
+
+    pub fn client_auth_optional<P: AsRef<Path>>(self, root_ca: P) -> Self {
+        self.client_auth(ClientAuth::Optional(root_ca.as_ref().to_path_buf()))
+    }
+
+    /// Supported application protocols, by order of preference (e.g. h2, http/1.1)
+    pub fn alpn<S: AsRef<str>>(mut self, protocols: &[S]) -> Self {
+        self.alpn = protocols
+            .iter()
+            .map(|p| p.as_ref().as_bytes().to_vec())
+            .collect();
+        self
+    }
+
+    /// Protocol versions (default: Tls 1.2 & 1.3)
+    pub fn versions(mut self, versions: &[&'static SupportedProtocolVersion]) -> Self {
+        self.versions = versions.to_vec();
+        self
+    }
+
+    pub fn build(self) -> Result<ServerConfig, TlsConfigError> {
+        let (certs, key) = match self.identity {
+            Identity::PemFiles { cert, key } => (load_certs(cert)?, load_private_key(key)?),
+            Identity::Der(certs, key) => (certs, key),
+        };
+
+        let builder = ServerConfig::builder_with_provider(provider::default_provider().into())
+            .with_protocol_versions(&self.versions)?;
+
+        let builder = match self.client_auth {
+            ClientAuth::None => builder.with_no_client_auth(),
+            ClientAuth::Optional(root_ca) => {
+                let root_store = load_root_store(root_ca)?;
+                let verifier = WebPkiClientVerifier::builder(root_store.into())
+                    .allow_unauthenticated()
+                    .build()?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            ClientAuth::Required(root_ca) => {
+                let root_store = load_root_store(root_ca)?;
+                let verifier = WebPkiClientVerifier::builder(root_store.into()).build()?;
+                builder.with_client_cert_verifier(verifier)
+            }
+        };
+
+        let mut config = builder.with_single_cert(certs, key)?;
+        config.alpn_protocols = self.alpn;
+        Ok(config)
+    }
+
+    pub fn build_acceptor(self) -> Result<TlsAcceptor, TlsConfigError> {
+        Ok(TlsAcceptor::from(Arc::new(self.build()?)))
+    }
+}
@@ -0,0 +1,41 @@
+use core::pin::Pin;
+
+use futures::task::{Context, Poll};
+use futures::Stream;
+
+// very basic Stream impl
+#[derive(Debug)]
+pub struct MyStream {
+    current: u32,
+    max: u32,
+}
+
+impl MyStream {
+    pub fn new(max: u32) -> MyStream {
+        MyStream { current: 0, max }
+    }
+}
+
+impl Stream for MyStream {
+    type Item = u32;
+
+    // Note: possible return values
+    //       * Poll::Pending: stream's next value is not ready yet
+    //       * Poll::Ready(Some(val)): stream produced a value and may produce further values
+    //       * Poll::Ready(None): stream has terminated
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let max = self.max;
+
+        match self.current {
+            ref mut x if *x < max => {
+                *x += 1;
+                Poll::Ready(Some(*x))
+            }
+            _ => Poll::Ready(None),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
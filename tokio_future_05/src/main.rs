@@ -5,18 +5,18 @@ use core::pin::Pin;
 
 // https://docs.rs/futures/latest/futures/
 use futures::Future;
-use futures::future::ok;
-use futures::TryFutureExt;
-use futures::future::err;
 use futures::task::Poll;
 use chrono::{DateTime, Duration, Utc};
-use futures::{Stream, StreamExt, Sink, SinkExt};
+use futures::{StreamExt, Sink, SinkExt};
 use futures::task::Context;
+use tokio_future_05::MyStream;
 
 // From https://dev.to/mindflavor/rust-futures-an-uneducated-short-and-hopefully-not-boring-tutorial---part5---streams-5i8
 // Stream trait for our future
 // Adapted to futures 0.3
 
+// Note: not used by main (only MyStream & MySink are), kept as an example
+#[allow(dead_code)]
 #[derive(Debug)]
 struct WaitForIt {
     message: String,
@@ -24,11 +24,12 @@ struct WaitForIt {
     polls: u64,
 }
 
+#[allow(dead_code)]
 impl WaitForIt {
 
     pub fn new(message: String, delay: Duration) -> Self {
         Self {
-            message: message,
+            message,
             until: Utc::now() + delay,
             polls: 0
         }
@@ -77,46 +78,6 @@ impl Stream for WaitForIt {
 }
 */
 
-// very basic Stream impl
-#[derive(Debug)]
-struct MyStream {
-    current: u32,
-    max: u32,
-}
-
-impl MyStream {
-    pub fn new(max: u32) -> MyStream {
-        MyStream { current: 0, max: max }
-    }
-}
-
-impl Stream for MyStream {
-
-    type Item = u32;
-
-    // Note: possible return values
-    //       * Poll::Pending: stream's next value is not ready yet
-    //       * Poll::Ready(Some(val)): stream produced a value and may produce further values
-    //       * Poll::Ready(None): stream has terminated
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-
-        let max = self.max;
-
-        match self.current {
-            ref mut x if *x < max => {
-                *x = *x + 1;
-                Poll::Ready(Some(*x))
-            }
-            _ => Poll::Ready(None),
-        }
-    }
-
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (0, None)
-    }
-
-}
-
 // very basic Sink impl
 
 use core::convert::Infallible;
@@ -193,7 +154,7 @@ fn main() {
 
     let mut my_stream_2 = MyStream::new(5);
     let rt = tokio::runtime::Runtime::new().unwrap();
-    let retval = rt.block_on(async {
+    rt.block_on(async {
         while let Some(value) = my_stream_2.next().await {
             println!("value: {}", value);
         }
@@ -202,9 +163,10 @@ fn main() {
     let mut my_stream_3 = MyStream::new(11);
     let mut sink_1 = MySink::new();
     let rt = tokio::runtime::Runtime::new().unwrap();
-    let retval = rt.block_on(async {
+    rt.block_on(async {
         while let Some(value) = my_stream_3.next().await {
-            sink_1.send(value).await;
+            // Infallible: the send cannot fail
+            let _ = sink_1.send(value).await;
         }
     });
 
@@ -1,15 +1,39 @@
 use std::error::Error;
 use std::str;
+use std::sync::Arc;
+use std::time::Duration;
 
+use tls_config_lib::{handshake, parse_args, HandshakeStats, TlsServerBuilder};
 use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
 
-async fn handle_conn(mut sock: TcpStream, buffer_len: usize) {
+/*
+ * An uppercase echo server (plain tcp or tls)
+ *
+ * run:
+ *
+ * cargo run --bin tokio_tcp_echo
+ *
+ * or with tls (certificates: see tokio_tcp_tls/certs):
+ *
+ * cargo run --bin tokio_tcp_echo -- --cert ../tokio_tcp_tls/certs/ca_signed/mydomain.com.crt \
+ *   --key ../tokio_tcp_tls/certs/ca_signed/mydomain.com.key [--client-ca root_ca.pem] \
+ *   [--handshake-timeout-ms 10000]
+ *
+ */
+
+const DEFAULT_HANDSHAKE_TIMEOUT_MS: u64 = 10_000;
+
+async fn handle_conn<S>(sock: S, buffer_len: usize)
+where
+    S: AsyncRead + AsyncWrite + std::fmt::Debug,
+{
     // same as handle_conn but with dynamic buffer_len + handle partial write
 
     println!("Got a connection: {:?}", sock);
-    let (mut reader, mut writer) = sock.split();
+    let (mut reader, mut writer) = tokio::io::split(sock);
 
     let mut buffer: Vec<u8> = vec![0; buffer_len];
 
@@ -52,16 +76,60 @@ async fn handle_conn(mut sock: TcpStream, buffer_len: usize) {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let (_, options) = parse_args();
+
+    let acceptor = match (options.get("cert"), options.get("key")) {
+        (Some(cert), Some(key)) => {
+            let builder = TlsServerBuilder::from_pem_files(cert, key);
+            let builder = match options.get("client-ca") {
+                Some(client_ca) => builder.client_auth_required(client_ca),
+                None => builder,
+            };
+            Some(builder.build_acceptor()?)
+        }
+        (None, None) => None,
+        _ => return Err("Tls requires both --cert and --key".into()),
+    };
+    let handshake_timeout = match options.get("handshake-timeout-ms") {
+        Some(ms) => Duration::from_millis(ms.parse::<u64>()?),
+        None => Duration::from_millis(DEFAULT_HANDSHAKE_TIMEOUT_MS),
+    };
+    let stats = Arc::new(HandshakeStats::default());
+
     let addr = "127.0.0.1:6161";
     let listener = TcpListener::bind(addr).await?;
-    println!("Listening on {}", addr);
+    println!(
+        "Listening on {} ({})",
+        addr,
+        if acceptor.is_some() { "tls" } else { "tcp" }
+    );
 
     loop {
-        let (sock, _) = listener.accept().await?;
+        let (sock, peer_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("[Tcp] accept error: {}", e);
+                continue;
+            }
+        };
 
         // Spawn a task to handle this connection
-        tokio::spawn(async move {
-            handle_conn(sock, 1024).await;
-        });
+        match acceptor.clone() {
+            Some(acceptor) => {
+                let stats = stats.clone();
+                tokio::spawn(async move {
+                    if let Some(stream) =
+                        handshake(&acceptor, sock, peer_addr, handshake_timeout, &stats).await
+                    {
+                        handle_conn(stream, 1024).await;
+                    }
+                });
+            }
+            None => {
+                tokio::spawn(async move {
+                    handle_conn(sock, 1024).await;
+                });
+            }
+        }
     }
 }
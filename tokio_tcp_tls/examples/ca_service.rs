@@ -0,0 +1,619 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+// Tls
+use tls_config_lib::{handshake, parse_args, HandshakeStats, TlsServerBuilder};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+// Certificate signing
+use rcgen::{
+    CertificateRevocationListParams, CertificateSigningRequestParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, Issuer, KeyIdMethod, KeyPair, KeyUsagePurpose, RevocationReason,
+    RevokedCertParams, SerialNumber,
+};
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+use x509_parser::parse_x509_certificate;
+
+// traits
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader as AsyncBufReader};
+
+// Easy error handling with async code
+type AResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/*
+ * A local certificate authority: sign certificate signing requests (CSR) received over an
+ * authenticated Tls connection (mTLS, client certificates must be issued by the same root CA)
+ *
+ * Issued certificates are recorded in an index (openssl index.txt format) and can be revoked,
+ * each revocation generates a new certificate revocation list (crl.pem)
+ *
+ * run:
+ *
+ * cd certs && ./ca_signed_client_auth.sh mydomain2.org && cd ..
+ * cargo run --example ca_service -- 127.0.0.1:7272 \
+ *   certs/ca_signed_client_auth/mydomain2.org.crt certs/ca_signed_client_auth/mydomain2.org.key \
+ *   certs/ca_signed_client_auth/root_ca.pem certs/ca_signed_client_auth/root_ca.key --admin-cn client1
+ *
+ * Protocol (one request per connection):
+ *
+ * SIGN [client|server]\n<CSR pem>  -> OK <serial>\n<certificate pem>
+ * REVOKE <serial>\n                -> OK\n<crl pem>
+ * CRL\n                            -> OK\n<crl pem>
+ * (on error)                       -> ERR <message>\n
+ *
+ * Only admins (--admin-cn) can revoke certificates, request a server certificate (unless the
+ * default profile is server) or a certificate for another subject: for other clients, the
+ * subject of the issued certificate is their own common name
+ *
+ * sign a CSR with:
+ *
+ * openssl genrsa -out client2.key 2048
+ * openssl req -new -key client2.key -out client2.csr -subj "/CN=client2"
+ * (echo "SIGN client"; cat client2.csr) | cargo run --example tls_client -- 127.0.0.1:7272 \
+ *   --ca certs/ca_signed_client_auth/root_ca.pem --servername mydomain2.org \
+ *   --cert certs/ca_signed_client_auth/client1.crt --key certs/ca_signed_client_auth/client1.key > client2.crt
+ *
+ */
+
+const USAGE: &str = "Use: cargo run --example ca_service -- 127.0.0.1:7272 cert.pem key.pem root_ca.pem root_ca.key \
+    [--index-dir certs/ca_signed_client_auth/ca_service] [--validity-days 365] [--profile client] \
+    [--crl-days 7] [--admin-cn admin1,admin2] [--handshake-timeout-ms 10000]";
+
+const DEFAULT_INDEX_DIR: &str = "certs/ca_signed_client_auth/ca_service";
+const DEFAULT_VALIDITY_DAYS: i64 = 365;
+const DEFAULT_CRL_DAYS: i64 = 7;
+const DEFAULT_HANDSHAKE_TIMEOUT_MS: u64 = 10_000;
+const FIRST_SERIAL: u64 = 0x1000;
+// Max size of a request (command + CSR)
+const MAX_REQUEST_LEN: usize = 64 * 1024;
+// Max time to receive a whole request (after the handshake)
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What an issued certificate can be used for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Profile {
+    Client,
+    Server,
+}
+
+impl std::str::FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(Profile::Client),
+            "server" => Ok(Profile::Server),
+            _ => Err(format!(
+                "Unknown profile: {} (expected: client or server)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CertStatus {
+    Valid,
+    Revoked(OffsetDateTime),
+}
+
+/// An index entry, e.g. a line of index.txt (same format as openssl ca):
+/// status expiration_date revocation_date serial filename subject
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    status: CertStatus,
+    expires: OffsetDateTime,
+    serial: u64,
+    subject: String,
+}
+
+/// Format a date as an ASN1 UTCTime (e.g. 261019120000Z) or, outside 1950-2049, as a
+/// GeneralizedTime (e.g. 20601019120000Z), as openssl does
+fn format_utc_time(t: OffsetDateTime) -> String {
+    let year = match t.year() {
+        1950..=2049 => format!("{:02}", t.year() % 100),
+        year => format!("{:04}", year),
+    };
+    format!(
+        "{}{:02}{:02}{:02}{:02}{:02}Z",
+        year,
+        u8::from(t.month()),
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+fn parse_utc_time(s: &str) -> AResult<OffsetDateTime> {
+    let s = s.strip_suffix('Z').ok_or("Invalid date: missing Z")?;
+    if !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("Invalid date: {}", s).into());
+    }
+    // UTCTime: 2 digits year (50-99: 19xx, 00-49: 20xx), GeneralizedTime: 4 digits year
+    let (year, s) = match s.len() {
+        12 => match s[..2].parse::<i32>()? {
+            yy @ 50.. => (1900 + yy, &s[2..]),
+            yy => (2000 + yy, &s[2..]),
+        },
+        14 => (s[..4].parse::<i32>()?, &s[4..]),
+        _ => return Err(format!("Invalid date: {}", s).into()),
+    };
+    let field = |i: usize| s[i..i + 2].parse::<u8>().unwrap();
+    let date = Date::from_calendar_date(year, Month::try_from(field(0))?, field(2))?;
+    let time = Time::from_hms(field(4), field(6), field(8))?;
+    Ok(PrimitiveDateTime::new(date, time).assume_utc())
+}
+
+impl IndexEntry {
+    fn to_line(&self) -> String {
+        let (status, revoked) = match self.status {
+            CertStatus::Valid => ("V", String::new()),
+            CertStatus::Revoked(t) => ("R", format_utc_time(t)),
+        };
+        format!(
+            "{}\t{}\t{}\t{:X}\tunknown\t{}",
+            status,
+            format_utc_time(self.expires),
+            revoked,
+            self.serial,
+            self.subject
+        )
+    }
+
+    fn from_line(line: &str) -> AResult<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 6 {
+            return Err(format!("Invalid index line: {}", line).into());
+        }
+        let status = match fields[0] {
+            "V" => CertStatus::Valid,
+            // Note: openssl can append a reason (e.g. 261019120000Z,keyCompromise)
+            "R" => CertStatus::Revoked(parse_utc_time(
+                fields[2].split(',').next().unwrap_or_default(),
+            )?),
+            s => return Err(format!("Unknown status in index: {}", s).into()),
+        };
+        Ok(Self {
+            status,
+            expires: parse_utc_time(fields[1])?,
+            serial: u64::from_str_radix(fields[3], 16)?,
+            subject: fields[5].to_string(),
+        })
+    }
+}
+
+/// On disk index of the issued certificates (+ issued certificates & crl)
+///
+/// index_dir/index.txt: one line per issued certificate
+/// index_dir/crlnumber: next crl number
+/// index_dir/issued/SERIAL.pem: issued certificates
+/// index_dir/crl.pem: latest crl
+#[derive(Debug)]
+struct CaIndex {
+    dir: PathBuf,
+    entries: Vec<IndexEntry>,
+    crl_number: u64,
+}
+
+impl CaIndex {
+    fn load(dir: &Path) -> AResult<Self> {
+        std::fs::create_dir_all(dir.join("issued"))?;
+
+        let index_path = dir.join("index.txt");
+        let entries = match std::fs::read_to_string(&index_path) {
+            Ok(content) => content
+                .lines()
+                .filter(|l| !l.is_empty())
+                .map(IndexEntry::from_line)
+                .collect::<AResult<Vec<IndexEntry>>>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        let crl_number = match std::fs::read_to_string(dir.join("crlnumber")) {
+            Ok(content) => u64::from_str_radix(content.trim(), 16)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 1,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            entries,
+            crl_number,
+        })
+    }
+
+    fn save(&self) -> AResult<()> {
+        let content: String = self
+            .entries
+            .iter()
+            .map(|e| format!("{}\n", e.to_line()))
+            .collect();
+        std::fs::write(self.dir.join("index.txt"), content)?;
+        std::fs::write(
+            self.dir.join("crlnumber"),
+            format!("{:X}\n", self.crl_number),
+        )?;
+        Ok(())
+    }
+
+    fn next_serial(&self) -> u64 {
+        self.entries
+            .iter()
+            .map(|e| e.serial + 1)
+            .max()
+            .unwrap_or(FIRST_SERIAL)
+    }
+
+    fn is_revoked(&self, serial: u64) -> bool {
+        self.entries
+            .iter()
+            .any(|e| e.serial == serial && e.status != CertStatus::Valid)
+    }
+}
+
+/// Certificate authority settings & state, shared between all connection tasks
+struct CertificateAuthority {
+    issuer: Issuer<'static, KeyPair>,
+    index: CaIndex,
+    validity: time::Duration,
+    default_profile: Profile,
+    crl_validity: time::Duration,
+    admin_cns: Vec<String>,
+}
+
+impl CertificateAuthority {
+    fn is_admin(&self, cn: &str) -> bool {
+        self.admin_cns.iter().any(|admin| admin == cn)
+    }
+
+    /// Sign a CSR (requested by the client `requester_cn`) and record the new certificate in the
+    /// index. Return the serial & the certificate (pem)
+    fn sign(
+        &mut self,
+        csr_pem: &str,
+        profile: Profile,
+        requester_cn: &str,
+    ) -> AResult<(u64, String)> {
+        let mut csr = CertificateSigningRequestParams::from_pem(csr_pem)
+            .map_err(|e| format!("Invalid CSR: {}", e))?;
+
+        let admin = self.is_admin(requester_cn);
+        if profile == Profile::Server && !admin && self.default_profile != Profile::Server {
+            return Err(format!(
+                "{} is not allowed to request a server certificate",
+                requester_cn
+            )
+            .into());
+        }
+
+        let serial = self.index.next_serial();
+        let now = OffsetDateTime::now_utc();
+        let params = &mut csr.params;
+        // Note: a client cannot impersonate another one (e.g. an admin), the requested subject is
+        //       replaced by its own common name (subjectAltNames are only kept for a server)
+        if !admin {
+            params.distinguished_name = DistinguishedName::new();
+            params
+                .distinguished_name
+                .push(DnType::CommonName, requester_cn);
+            if profile == Profile::Client {
+                params.subject_alt_names.clear();
+            }
+        }
+        params.serial_number = Some(SerialNumber::from(serial));
+        params.not_before = now;
+        params.not_after = now + self.validity;
+        params.use_authority_key_identifier_extension = true;
+        // Note: the profile overrides whatever usage is requested in the CSR (e.g. never issue a CA)
+        params.is_ca = IsCa::ExplicitNoCa;
+        match profile {
+            Profile::Client => {
+                params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+                params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+            }
+            Profile::Server => {
+                if params.subject_alt_names.is_empty() {
+                    return Err("A server certificate requires a subjectAltName".into());
+                }
+                params.key_usages = vec![
+                    KeyUsagePurpose::DigitalSignature,
+                    KeyUsagePurpose::KeyEncipherment,
+                ];
+                params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+            }
+        }
+
+        let cert = csr.signed_by(&self.issuer)?;
+        let (_, x509) = parse_x509_certificate(cert.der())?;
+        let cert_pem = cert.pem();
+
+        std::fs::write(
+            self.index
+                .dir
+                .join("issued")
+                .join(format!("{:X}.pem", serial)),
+            &cert_pem,
+        )?;
+        self.index.entries.push(IndexEntry {
+            status: CertStatus::Valid,
+            expires: now + self.validity,
+            serial,
+            subject: x509.subject().to_string(),
+        });
+        self.index.save()?;
+
+        Ok((serial, cert_pem))
+    }
+
+    fn revoke(&mut self, serial: u64) -> AResult<()> {
+        let entry = self
+            .index
+            .entries
+            .iter_mut()
+            .find(|e| e.serial == serial)
+            .ok_or_else(|| format!("Unknown serial: {:X}", serial))?;
+        if entry.status != CertStatus::Valid {
+            return Err(format!("Certificate {:X} is already revoked", serial).into());
+        }
+        entry.status = CertStatus::Revoked(OffsetDateTime::now_utc());
+        self.index.save()?;
+        Ok(())
+    }
+
+    /// Generate a new crl (from the index) and write it to index_dir/crl.pem
+    fn generate_crl(&mut self) -> AResult<String> {
+        let now = OffsetDateTime::now_utc();
+        let revoked_certs = self
+            .index
+            .entries
+            .iter()
+            .filter_map(|e| match e.status {
+                CertStatus::Revoked(revocation_time) => Some(RevokedCertParams {
+                    serial_number: SerialNumber::from(e.serial),
+                    revocation_time,
+                    reason_code: Some(RevocationReason::Unspecified),
+                    invalidity_date: None,
+                }),
+                CertStatus::Valid => None,
+            })
+            .collect();
+
+        let crl = CertificateRevocationListParams {
+            this_update: now,
+            next_update: now + self.crl_validity,
+            crl_number: SerialNumber::from(self.index.crl_number),
+            issuing_distribution_point: None,
+            revoked_certs,
+            key_identifier_method: KeyIdMethod::Sha256,
+        }
+        .signed_by(&self.issuer)?;
+        let crl_pem = crl.pem()?;
+
+        std::fs::write(self.index.dir.join("crl.pem"), &crl_pem)?;
+        self.index.crl_number += 1;
+        self.index.save()?;
+        Ok(crl_pem)
+    }
+}
+
+/// Client certificate serial & common name
+fn client_identity(stream: &TlsStream<TcpStream>) -> AResult<(u64, String)> {
+    let (_, conn) = stream.get_ref();
+    let cert = conn
+        .peer_certificates()
+        .and_then(|c| c.first())
+        .ok_or("No client certificate")?;
+    let (_, x509) = parse_x509_certificate(cert.as_ref())?;
+    let serial = u64::try_from(x509.serial.clone()).unwrap_or(u64::MAX);
+    let cn = x509
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    Ok((serial, cn))
+}
+
+/// Read the request (command line + CSR for SIGN), process it and return the response
+async fn handle_request(
+    reader: &mut AsyncBufReader<tokio::io::ReadHalf<TlsStream<TcpStream>>>,
+    client_cn: &str,
+    ca: &Mutex<CertificateAuthority>,
+) -> AResult<String> {
+    let mut command = String::new();
+    reader
+        .take(MAX_REQUEST_LEN as u64)
+        .read_line(&mut command)
+        .await?;
+    let mut words = command.split_whitespace();
+
+    match (words.next(), words.next()) {
+        (Some("SIGN"), profile) => {
+            let profile = match profile {
+                Some(p) => p.parse::<Profile>()?,
+                None => ca.lock().unwrap().default_profile,
+            };
+            // Read the CSR (until the pem footer), the whole request is at most MAX_REQUEST_LEN
+            let mut csr_pem = String::new();
+            let mut csr_reader = reader.take((MAX_REQUEST_LEN - command.len()) as u64);
+            while !csr_pem.contains("-----END CERTIFICATE REQUEST-----") {
+                if csr_reader.read_line(&mut csr_pem).await? == 0 {
+                    return Err("Incomplete or too large CSR".into());
+                }
+            }
+            let (serial, cert_pem) = ca.lock().unwrap().sign(&csr_pem, profile, client_cn)?;
+            println!(
+                "[CA] {:X} issued ({:?} profile) - requested by: {}",
+                serial, profile, client_cn
+            );
+            Ok(format!("OK {:X}\n{}", serial, cert_pem))
+        }
+        (Some("REVOKE"), Some(serial)) => {
+            let serial = u64::from_str_radix(serial, 16)?;
+            let mut ca = ca.lock().unwrap();
+            if ca.admin_cns.is_empty() {
+                return Err("Revocation is disabled (no --admin-cn)".into());
+            }
+            if !ca.is_admin(client_cn) {
+                return Err(format!("{} is not allowed to revoke certificates", client_cn).into());
+            }
+            ca.revoke(serial)?;
+            let crl_pem = ca.generate_crl()?;
+            println!("[CA] {:X} revoked - requested by: {}", serial, client_cn);
+            Ok(format!("OK\n{}", crl_pem))
+        }
+        (Some("CRL"), None) => {
+            let crl_pem = std::fs::read_to_string(ca.lock().unwrap().index.dir.join("crl.pem"))?;
+            Ok(format!("OK\n{}", crl_pem))
+        }
+        _ => Err(format!("Invalid request: {:?}", command.trim_end()).into()),
+    }
+}
+
+async fn handshake_and_handle(
+    acceptor: TlsAcceptor,
+    socket: TcpStream,
+    peer_addr: SocketAddr,
+    handshake_timeout: Duration,
+    stats: Arc<HandshakeStats>,
+    ca: Arc<Mutex<CertificateAuthority>>,
+) -> AResult<()> {
+    let Some(stream) = handshake(&acceptor, socket, peer_addr, handshake_timeout, &stats).await
+    else {
+        return Ok(());
+    };
+
+    let (client_serial, client_cn) = client_identity(&stream)?;
+    let (reader, mut writer) = tokio::io::split(stream);
+
+    // Note: the client verifier only checks the certificate chain, revoked clients are refused here
+    let response = if ca.lock().unwrap().index.is_revoked(client_serial) {
+        println!("[CA] {} ({:X}) refused: revoked", peer_addr, client_serial);
+        "ERR client certificate revoked\n".to_string()
+    } else {
+        let mut reader = AsyncBufReader::new(reader);
+        let request = handle_request(&mut reader, &client_cn, &ca);
+        match tokio::time::timeout(REQUEST_TIMEOUT, request)
+            .await
+            .unwrap_or_else(|_| Err(format!("no request after {:?}", REQUEST_TIMEOUT).into()))
+        {
+            Ok(response) => response,
+            Err(e) => {
+                println!("[CA] {} ({}) request error: {}", peer_addr, client_cn, e);
+                format!("ERR {}\n", e)
+            }
+        }
+    };
+
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await?;
+    Ok(())
+}
+
+async fn serve() -> AResult<()> {
+    let (arg, options) = parse_args();
+
+    let (addr, cert, key, root_ca_cert, root_ca_key) = match arg.len() {
+        5 => (&arg[0], &arg[1], &arg[2], &arg[3], &arg[4]),
+        _ => panic!("{}", USAGE),
+    };
+
+    let index_dir = PathBuf::from(
+        options
+            .get("index-dir")
+            .map_or(DEFAULT_INDEX_DIR, |d| d.as_str()),
+    );
+    let validity_days = match options.get("validity-days") {
+        Some(days) => days.parse::<i64>()?,
+        None => DEFAULT_VALIDITY_DAYS,
+    };
+    let crl_days = match options.get("crl-days") {
+        Some(days) => days.parse::<i64>()?,
+        None => DEFAULT_CRL_DAYS,
+    };
+    let default_profile = match options.get("profile") {
+        Some(profile) => profile.parse::<Profile>()?,
+        None => Profile::Client,
+    };
+    let handshake_timeout = match options.get("handshake-timeout-ms") {
+        Some(ms) => Duration::from_millis(ms.parse::<u64>()?),
+        None => Duration::from_millis(DEFAULT_HANDSHAKE_TIMEOUT_MS),
+    };
+    let admin_cns: Vec<String> = options
+        .get("admin-cn")
+        .map(|cns| cns.split(',').map(|cn| cn.to_string()).collect())
+        .unwrap_or_default();
+
+    println!("index dir: {}", index_dir.display());
+    println!(
+        "validity: {} days, default profile: {:?}, crl validity: {} days",
+        validity_days, default_profile, crl_days
+    );
+    println!(
+        "admins (allowed to revoke & sign any subject): {:?}",
+        admin_cns
+    );
+
+    // Root CA (certificate signing)
+    let ca_key = KeyPair::from_pem(&std::fs::read_to_string(root_ca_key)?)?;
+    let issuer = Issuer::from_ca_cert_pem(&std::fs::read_to_string(root_ca_cert)?, ca_key)?;
+    let mut ca = CertificateAuthority {
+        issuer,
+        index: CaIndex::load(&index_dir)?,
+        validity: time::Duration::days(validity_days),
+        default_profile,
+        crl_validity: time::Duration::days(crl_days),
+        admin_cns,
+    };
+    ca.generate_crl()?;
+    println!(
+        "{} certificate(s) in index, crl written to: {}",
+        ca.index.entries.len(),
+        index_dir.join("crl.pem").display()
+    );
+    let ca = Arc::new(Mutex::new(ca));
+
+    // Tls server, clients must present a certificate issued by the root CA
+    let acceptor = TlsServerBuilder::from_pem_files(cert, key)
+        .client_auth_required(root_ca_cert)
+        .build_acceptor()?;
+    let stats = Arc::new(HandshakeStats::default());
+
+    let listener = TcpListener::bind(&addr[..]).await?;
+    println!("[Tcp/Tls] Listening on {}", addr);
+    loop {
+        let (socket, peer_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("[Tcp] accept error: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let stats = stats.clone();
+        let ca = ca.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) =
+                handshake_and_handle(acceptor, socket, peer_addr, handshake_timeout, stats, ca)
+                    .await
+            {
+                println!("[CA] {} error: {}", peer_addr, e);
+            }
+        });
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    println!("Starting certificate authority service");
+    if let Err(e) = serve().await {
+        println!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,253 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use rustls_pki_types::CertificateDer;
+use sha1::{Digest, Sha1};
+use tls_config_lib::{fingerprint, load_certs, parse_args, to_hex, TlsConfigError};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::parse_x509_certificate;
+
+type AFnResult<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/*
+ * Print certificate details (subject, issuer, validity, SANs, key usage, fingerprints)
+ * and validate certificate chains
+ *
+ * run:
+ *
+ * cargo run --example cert_inspect -- certs/ca_signed/mydomain.com.crt --root certs/ca_signed/root_ca.pem
+ *
+ * or check a whole directory (every .pem / .crt file):
+ *
+ * cargo run --example cert_inspect -- certs/ca_signed_client_auth --root certs/ca_signed_client_auth/root_ca.pem --expiry-days 60
+ *
+ * Exit code is 1 if any problem is found (invalid chain, expired or soon expiring certificate...)
+ *
+ */
+
+const USAGE: &str = "Please run: cargo run --example cert_inspect -- bundle.pem|cert_dir ... \
+    [--root root_ca.pem] [--expiry-days 30]";
+
+const DEFAULT_EXPIRY_DAYS: i64 = 30;
+
+/// Files to inspect: a file is used as is, a directory is replaced by its .pem / .crt files
+fn collect_files(paths: &[String]) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths.iter().map(PathBuf::from) {
+        if path.is_dir() {
+            let mut dir_files: Vec<PathBuf> = std::fs::read_dir(&path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| {
+                    p.extension()
+                        .is_some_and(|ext| ext == "pem" || ext == "crt")
+                })
+                .collect();
+            dir_files.sort();
+            files.extend(dir_files);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn print_cert(index: usize, der: &CertificateDer<'_>, x509: &X509Certificate<'_>) {
+    println!("  [{}] subject: {}", index, x509.subject());
+    println!("      issuer: {}", x509.issuer());
+    println!("      serial: {}", x509.raw_serial_as_string());
+    println!(
+        "      validity: {} -> {}",
+        x509.validity().not_before,
+        x509.validity().not_after
+    );
+    match x509.subject_alternative_name() {
+        Ok(Some(san)) => {
+            let names: Vec<String> = san
+                .value
+                .general_names
+                .iter()
+                .map(|n| n.to_string())
+                .collect();
+            println!("      SANs: {}", names.join(", "));
+        }
+        Ok(None) => println!("      SANs: -"),
+        Err(e) => println!("      SANs: invalid extension ({})", e),
+    }
+    match x509.key_usage() {
+        Ok(Some(ku)) => println!("      key usage: {}", ku.value),
+        Ok(None) => println!("      key usage: -"),
+        Err(e) => println!("      key usage: invalid extension ({})", e),
+    }
+    if let Ok(Some(eku)) = x509.extended_key_usage() {
+        let eku = eku.value;
+        let usages: Vec<&str> = [
+            (eku.any, "any"),
+            (eku.server_auth, "serverAuth"),
+            (eku.client_auth, "clientAuth"),
+            (eku.code_signing, "codeSigning"),
+            (eku.email_protection, "emailProtection"),
+            (eku.time_stamping, "timeStamping"),
+            (eku.ocsp_signing, "ocspSigning"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect();
+        println!("      extended key usage: {}", usages.join(", "));
+    }
+    println!("      CA: {}", x509.is_ca());
+    println!(
+        "      sha1: {}",
+        to_hex(Sha1::digest(der.as_ref()).as_slice())
+    );
+    println!("      sha256: {}", fingerprint(der));
+}
+
+/// Check that a certificate is currently valid and does not expire within `expiry_days`
+fn check_validity(x509: &X509Certificate<'_>, expiry_days: i64) -> Option<String> {
+    match x509.validity().time_to_expiration() {
+        None => Some(format!(
+            "{}: expired or not yet valid ({} -> {})",
+            x509.subject(),
+            x509.validity().not_before,
+            x509.validity().not_after
+        )),
+        Some(left) if left.whole_days() < expiry_days => Some(format!(
+            "{}: expires in {} day(s) ({})",
+            x509.subject(),
+            left.whole_days(),
+            x509.validity().not_after
+        )),
+        Some(_) => None,
+    }
+}
+
+/// Check that `cert` has been issued (and signed) by `issuer`
+fn check_issued_by(cert: &X509Certificate<'_>, issuer: &X509Certificate<'_>) -> Option<String> {
+    if cert.issuer().as_raw() != issuer.subject().as_raw() {
+        return Some(format!(
+            "{}: issuer ({}) is not the next certificate ({})",
+            cert.subject(),
+            cert.issuer(),
+            issuer.subject()
+        ));
+    }
+    if !issuer.is_ca() {
+        return Some(format!("{}: issuer is not a CA", issuer.subject()));
+    }
+    cert.verify_signature(Some(issuer.public_key()))
+        .err()
+        .map(|e| format!("{}: invalid signature ({})", cert.subject(), e))
+}
+
+/// Inspect a pem bundle (leaf certificate first) & validate the chain, return the problems found
+fn inspect(
+    path: &Path,
+    root: Option<&X509Certificate<'_>>,
+    expiry_days: i64,
+) -> AFnResult<Vec<String>> {
+    // Note: a file without certificate (e.g. a key in a directory) is not a problem
+    let ders = match load_certs(path) {
+        Ok(ders) => ders,
+        Err(TlsConfigError::NoCertificate(_)) => vec![],
+        Err(e) => return Err(e.into()),
+    };
+    println!("{}: {} certificate(s)", path.display(), ders.len());
+    if ders.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut certs = vec![];
+    for der in ders.iter() {
+        let (_, x509) = parse_x509_certificate(der.as_ref())
+            .map_err(|e| format!("{}: unable to parse certificate: {}", path.display(), e))?;
+        certs.push(x509);
+    }
+
+    let mut problems = vec![];
+    for (i, (der, x509)) in ders.iter().zip(certs.iter()).enumerate() {
+        print_cert(i, der, x509);
+        problems.extend(check_validity(x509, expiry_days));
+    }
+
+    // Chain: each certificate must be issued by the next one
+    for pair in certs.windows(2) {
+        problems.extend(check_issued_by(&pair[0], &pair[1]));
+    }
+
+    // Then the last certificate must be the root or issued by the root
+    let last = certs.last().unwrap();
+    match root {
+        // Note: the root itself can be part of the bundle
+        Some(root)
+            if last.subject().as_raw() == root.subject().as_raw()
+                && last.public_key().raw == root.public_key().raw => {}
+        Some(root) => problems.extend(check_issued_by(last, root)),
+        None if last.subject().as_raw() == last.issuer().as_raw() => {
+            if let Err(e) = last.verify_signature(None) {
+                problems.push(format!(
+                    "{}: invalid self signature ({})",
+                    last.subject(),
+                    e
+                ));
+            }
+        }
+        None => println!("  Note: no root (--root), chain is not verified up to a root CA"),
+    }
+
+    let problems: Vec<String> = problems
+        .into_iter()
+        .map(|p| format!("{}: {}", path.display(), p))
+        .collect();
+    Ok(problems)
+}
+
+fn main() -> AFnResult<()> {
+    let (arg, options) = parse_args();
+    if arg.is_empty() {
+        panic!("{}", USAGE);
+    }
+
+    let expiry_days = match options.get("expiry-days") {
+        Some(days) => days.parse::<i64>()?,
+        None => DEFAULT_EXPIRY_DAYS,
+    };
+
+    let mut problems = vec![];
+
+    let root_der = match options.get("root") {
+        // Note: load_certs returns an error if the file has no certificate
+        Some(root_path) => Some(load_certs(root_path)?.remove(0)),
+        None => None,
+    };
+    let root = match root_der.as_ref() {
+        Some(der) => {
+            let (_, root) = parse_x509_certificate(der.as_ref())
+                .map_err(|e| format!("Unable to parse root CA: {}", e))?;
+            println!("Root CA: {}", root.subject());
+            if let Err(e) = root.verify_signature(None) {
+                problems.push(format!("root CA: invalid self signature ({})", e));
+            }
+            problems.extend(check_validity(&root, expiry_days).map(|p| format!("root CA: {}", p)));
+            Some(root)
+        }
+        None => None,
+    };
+
+    for file in collect_files(&arg)? {
+        match inspect(&file, root.as_ref(), expiry_days) {
+            Ok(p) => problems.extend(p),
+            Err(e) => problems.push(format!("{}: {}", file.display(), e)),
+        }
+    }
+
+    if problems.is_empty() {
+        println!("No problem found");
+        Ok(())
+    } else {
+        println!("{} problem(s) found:", problems.len());
+        for problem in problems.iter() {
+            println!("  - {}", problem);
+        }
+        std::process::exit(1);
+    }
+}
@@ -1,23 +1,12 @@
-#![allow(clippy::bind_instead_of_map)]
-
 use std::env;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::net::{TcpListener, TcpStream};
 // Tls
-use rustls_pemfile::{
-    certs,
-    pkcs8_private_keys,
-    // rsa_private_keys
-};
-use rustls_pki_types::{CertificateDer, PrivateKeyDer};
-use tokio_rustls::{
-    rustls::ServerConfig, // TlsStream
-    TlsAcceptor,
-};
+use tls_config_lib::{handshake, load_certs, load_private_key, HandshakeStats, TlsServerBuilder};
 
 // traits
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
@@ -31,30 +20,13 @@ use tokio::io::{
 // Easy error handling with async code
 type AResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+const DEFAULT_HANDSHAKE_TIMEOUT_MS: u64 = 10_000;
+
 // Dbg
 
 use rustls_pemfile::{read_one, Item};
 use std::iter;
 
-pub fn load_certs<P>(path: P) -> std::io::Result<Vec<CertificateDer<'static>>>
-where
-    P: AsRef<Path>,
-{
-    let certfile = File::open(path).expect("cannot open certificate file");
-    let mut reader = BufReader::new(certfile);
-    certs(&mut reader).collect()
-}
-
-pub fn load_keys<P>(path: P) -> std::io::Result<Vec<PrivateKeyDer<'static>>>
-where
-    P: AsRef<Path>,
-{
-    // TODO: support other keys
-    pkcs8_private_keys(&mut BufReader::new(File::open(path.as_ref())?))
-        .map(|k_| k_.and_then(|k| Ok(PrivateKeyDer::Pkcs8(k))))
-        .collect()
-}
-
 async fn handle_conn(
     reader: &mut ReadHalf<tokio_rustls::server::TlsStream<TcpStream>>,
     writer: &mut WriteHalf<tokio_rustls::server::TlsStream<TcpStream>>,
@@ -113,18 +85,21 @@ async fn handle_conn(
 
 async fn serve() -> AResult<()> {
     // Skip args[0] (cmd line string) and only take first
-    let arg: Vec<String> = env::args().skip(1).take(3).collect();
+    let arg: Vec<String> = env::args().skip(1).take(4).collect();
 
     // let empty_str = String::new();
-    let panic_msg = "Use: cargo run -- 127.0.0.1:7070 cert.pem key.pem";
-    let (addr, cert, key, _enable_tls) = match arg.len() {
+    let panic_msg = "Use: cargo run -- 127.0.0.1:7070 cert.pem key.pem [handshake_timeout_ms]";
+    let (addr, cert, key, handshake_timeout_ms) = match arg.len() {
         0..=2 => panic!("{}", panic_msg),
-        3 => (&arg[0], &arg[1], &arg[2], true),
+        3 => (&arg[0], &arg[1], &arg[2], DEFAULT_HANDSHAKE_TIMEOUT_MS),
+        4 => (&arg[0], &arg[1], &arg[2], arg[3].parse::<u64>()?),
         _ => panic!("{}", panic_msg),
     };
+    let handshake_timeout = Duration::from_millis(handshake_timeout_ms);
 
     println!("arg 1: {}", cert);
     println!("arg 2: {}", key);
+    println!("handshake timeout: {:?}", handshake_timeout);
     // let enable_tls = !cert.is_empty() && !key.is_empty();
     // println!("Enable tls: {}", enable_tls);
 
@@ -144,22 +119,35 @@ async fn serve() -> AResult<()> {
 
     let certs = load_certs(cert)?;
     println!("certs: {:?}", certs);
-    let mut keys = load_keys(key)?;
-    println!("keys: {:?}", keys);
+    let key = load_private_key(key)?;
+    println!("key: {:?}", key);
 
-    let config = ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(certs, keys.pop().ok_or("Unable to read key")?)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
-    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let acceptor = TlsServerBuilder::from_der(certs, key).build_acceptor()?;
+
+    let stats = Arc::new(HandshakeStats::default());
 
     let listener = TcpListener::bind(&addr[..]).await?;
     println!("[Tcp/Tls] Listening on {}", addr);
     loop {
-        let (socket, _addr) = listener.accept().await?;
-        let stream = acceptor.accept(socket).await?;
-        let (mut reader, mut writer) = tokio::io::split(stream);
-        tokio::spawn(async move { handle_conn(&mut reader, &mut writer, 1024).await });
+        // Note: accept errors (e.g. too many open files) are not fatal for the server
+        let (socket, peer_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("[Tcp] accept error: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let stats = stats.clone();
+
+        tokio::spawn(async move {
+            if let Some(stream) =
+                handshake(&acceptor, socket, peer_addr, handshake_timeout, &stats).await
+            {
+                let (mut reader, mut writer) = tokio::io::split(stream);
+                handle_conn(&mut reader, &mut writer, 1024).await
+            }
+        });
     }
 }
 
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use rustls::client::ClientConnection;
+use rustls::HandshakeKind;
+use rustls_pki_types::ServerName;
+use tls_config_lib::{
+    fingerprint, parse_args, parse_fingerprint, parse_versions, TlsClientBuilder, TlsConfigError,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use x509_parser::parse_x509_certificate;
+
+type AFnResult<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/*
+ * A netcat like Tls client: stdin is sent to the server, server data is written to stdout
+ *
+ * Note: all diagnostic messages are written to stderr so stdout only contains server data
+ *
+ * run:
+ *
+ * cargo run --example tls_client -- 127.0.0.1:6161 --ca certs/ca_signed/root_ca.pem --servername mydomain.com
+ *
+ * with client auth (mTLS):
+ *
+ * cargo run --example tls_client -- 127.0.0.1:6161 --ca certs/ca_signed_client_auth/root_ca.pem \
+ *   --servername mydomain2.org \
+ *   --cert certs/ca_signed_client_auth/client1.crt --key certs/ca_signed_client_auth/client1.key
+ *
+ * self signed certificate (no CA, the server certificate sha256 fingerprint is checked instead):
+ *
+ * cargo run --example tls_client -- 127.0.0.1:6161 --insecure-pin 3f:a2:...
+ *
+ */
+
+const USAGE: &str = "Please run: cargo run --example tls_client -- ip:port \
+    [--ca root_ca.pem] [--cert client.crt --key client.key] [--servername mydomain.com] \
+    [--alpn h2,http/1.1] [--tls-versions 1.2,1.3] [--insecure-pin sha256_fingerprint] [--reconnect K]";
+
+/// Handshake durations, grouped by handshake kind (full vs resumed)
+#[derive(Debug, Default)]
+struct ResumptionReport {
+    full: Vec<Duration>,
+    resumed: Vec<Duration>,
+}
+
+impl ResumptionReport {
+    fn add(&mut self, kind: HandshakeKind, duration: Duration) {
+        match kind {
+            HandshakeKind::Resumed => self.resumed.push(duration),
+            _ => self.full.push(duration),
+        }
+    }
+
+    fn print(&self) {
+        for (name, durations) in [("full", &self.full), ("resumed", &self.resumed)] {
+            match (durations.iter().min(), durations.iter().max()) {
+                (Some(min), Some(max)) => {
+                    let avg = durations.iter().sum::<Duration>() / durations.len() as u32;
+                    eprintln!(
+                        "{} handshakes: {} - min: {:?}, avg: {:?}, max: {:?}",
+                        name,
+                        durations.len(),
+                        min,
+                        avg,
+                        max
+                    );
+                }
+                _ => eprintln!("{} handshakes: 0", name),
+            }
+        }
+    }
+}
+
+fn build_connector(options: &HashMap<String, String>) -> AFnResult<TlsConnector> {
+    let mut builder = TlsClientBuilder::new();
+    if let Some(root_ca_path) = options.get("ca") {
+        builder = builder.root_ca(root_ca_path);
+    }
+    if let Some(pin) = options.get("insecure-pin") {
+        builder = builder.pin(parse_fingerprint(pin)?);
+    }
+    builder = match (options.get("cert"), options.get("key")) {
+        (Some(cert), Some(key)) => builder.client_cert(cert, key),
+        (None, None) => builder,
+        _ => return Err("Client auth requires both --cert and --key".into()),
+    };
+    if let Some(alpn) = options.get("alpn") {
+        builder = builder.alpn(&alpn.split(',').collect::<Vec<&str>>());
+    }
+    if let Some(versions) = options.get("tls-versions") {
+        builder = builder.versions(&parse_versions(versions)?);
+    }
+
+    match builder.build_connector() {
+        Err(TlsConfigError::NoTrustAnchor) => {
+            Err("A root CA (--ca) or a certificate fingerprint (--insecure-pin) is required".into())
+        }
+        connector => Ok(connector?),
+    }
+}
+
+fn print_connection_info(conn: &ClientConnection, handshake_duration: Duration) {
+    eprintln!(
+        "Handshake: {:?} in {:?}",
+        conn.handshake_kind().unwrap_or(HandshakeKind::Full),
+        handshake_duration
+    );
+    eprintln!("Protocol version: {:?}", conn.protocol_version());
+    eprintln!(
+        "Cipher suite: {:?}",
+        conn.negotiated_cipher_suite().map(|s| s.suite())
+    );
+    eprintln!(
+        "Alpn: {:?}",
+        conn.alpn_protocol().map(String::from_utf8_lossy)
+    );
+
+    let chain = conn.peer_certificates().unwrap_or_default();
+    eprintln!("Peer certificate chain ({} certificate(s)):", chain.len());
+    for (i, cert) in chain.iter().enumerate() {
+        match parse_x509_certificate(cert.as_ref()) {
+            Ok((_, x509)) => {
+                eprintln!("  [{}] subject: {}", i, x509.subject());
+                eprintln!("      issuer: {}", x509.issuer());
+                eprintln!(
+                    "      validity: {} -> {}",
+                    x509.validity().not_before,
+                    x509.validity().not_after
+                );
+            }
+            Err(e) => eprintln!("  [{}] unable to parse certificate: {}", i, e),
+        }
+        eprintln!("      sha256: {}", fingerprint(cert));
+    }
+}
+
+async fn connect(
+    connector: &TlsConnector,
+    addr: &str,
+    domain: ServerName<'static>,
+) -> AFnResult<(TlsStream<TcpStream>, Duration)> {
+    let stream = TcpStream::connect(addr).await?;
+    let start = Instant::now();
+    let stream = connector.connect(domain, stream).await?;
+    Ok((stream, start.elapsed()))
+}
+
+/// Close the connection right after the handshake
+/// Note: the server may send session tickets after the handshake so we read until EOF
+async fn close(mut stream: TlsStream<TcpStream>) -> AFnResult<()> {
+    stream.shutdown().await?;
+    let mut buffer = vec![];
+    match stream.read_to_end(&mut buffer).await {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Send stdin to the server and server data to stdout, until both sides reach EOF
+async fn stream_stdio(stream: TlsStream<TcpStream>) -> AFnResult<()> {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+
+    let upload = async {
+        let mut stdin = tokio::io::stdin();
+        tokio::io::copy(&mut stdin, &mut writer).await?;
+        // stdin EOF: send close_notify but keep reading server data
+        writer.shutdown().await?;
+        Ok::<(), std::io::Error>(())
+    };
+
+    let download = async {
+        let mut stdout = tokio::io::stdout();
+        let res = tokio::io::copy(&mut reader, &mut stdout).await;
+        stdout.flush().await?;
+        match res {
+            Ok(_) => Ok(()),
+            // Note: our example servers close the connection without sending close_notify
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                eprintln!("Server closed the connection (no close_notify)");
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    };
+
+    tokio::try_join!(upload, download)?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> AFnResult<()> {
+    let (arg, options) = parse_args();
+
+    let addr = match arg.len() {
+        1 => &arg[0],
+        _ => panic!("{}", USAGE),
+    };
+    let reconnects = match options.get("reconnect") {
+        Some(k) => k.parse::<usize>()?,
+        None => 0,
+    };
+
+    let connector = build_connector(&options)?;
+
+    // Note: this should be set to your value in cert / subjectAltName
+    let servername = match options.get("servername") {
+        Some(servername) => servername.clone(),
+        None => addr
+            .rsplit_once(':')
+            .map_or(addr.clone(), |(host, _)| host.to_string()),
+    };
+    let domain = ServerName::try_from(servername)
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid domain"))?;
+
+    // Note: the connector (and its ClientConfig) is shared by all connections, so the sessions
+    //       stored by the previous connections can be resumed by the next ones
+    let mut report = ResumptionReport::default();
+    for _ in 0..reconnects {
+        let (stream, duration) = connect(&connector, addr, domain.clone()).await?;
+        let kind = stream
+            .get_ref()
+            .1
+            .handshake_kind()
+            .unwrap_or(HandshakeKind::Full);
+        eprintln!("Handshake: {:?} in {:?}", kind, duration);
+        report.add(kind, duration);
+        close(stream).await?;
+    }
+
+    let (stream, duration) = connect(&connector, addr, domain).await?;
+    print_connection_info(stream.get_ref().1, duration);
+    if reconnects > 0 {
+        report.add(
+            stream
+                .get_ref()
+                .1
+                .handshake_kind()
+                .unwrap_or(HandshakeKind::Full),
+            duration,
+        );
+        report.print();
+    }
+
+    stream_stdio(stream).await
+}
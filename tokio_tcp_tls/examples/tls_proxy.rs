@@ -0,0 +1,215 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+// Tls
+use tls_config_lib::{handshake, parse_args, HandshakeStats, TlsServerBuilder};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use x509_parser::parse_x509_certificate;
+
+// traits
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+// Easy error handling with async code
+type AResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/*
+ * A Tls terminating proxy: accept Tls connections (optionally with client auth) and forward the
+ * decrypted byte stream to a plaintext backend (e.g. tokio_tcp_echo)
+ *
+ * run:
+ *
+ * cargo run --bin tokio_tcp_echo
+ * cargo run --example tls_proxy -- 127.0.0.1:7171 certs/ca_signed/mydomain.com.crt certs/ca_signed/mydomain.com.key 127.0.0.1:6161
+ *
+ * test with:
+ *
+ * cargo run --example tls_client -- 127.0.0.1:7171 --ca certs/ca_signed/root_ca.pem --servername mydomain.com
+ *
+ * with client auth (mTLS) + client identity sent to the backend:
+ *
+ * cargo run --example tls_proxy -- 127.0.0.1:7171 certs/ca_signed_client_auth/mydomain2.org.crt \
+ *   certs/ca_signed_client_auth/mydomain2.org.key 127.0.0.1:6161 \
+ *   --client-ca certs/ca_signed_client_auth/root_ca.pem --identity-headers
+ *
+ */
+
+const USAGE: &str = "Use: cargo run --example tls_proxy -- 127.0.0.1:7171 cert.pem key.pem backend_ip:port \
+    [--client-ca root_ca.pem] [--identity-headers] [--connect-timeout-ms 5000] [--handshake-timeout-ms 10000]";
+
+const DEFAULT_HANDSHAKE_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 5_000;
+
+#[derive(Debug)]
+struct ProxyConfig {
+    backend_addr: String,
+    handshake_timeout: Duration,
+    connect_timeout: Duration,
+    identity_headers: bool,
+}
+
+/// Client identity (from the client certificate), sent to the backend before any client data:
+///
+/// X-Client-Cert-Subject: CN=client1
+/// X-Client-Cert-Issuer: CN=My Root CA
+/// X-Client-Cert-Serial: 75:a8:...
+/// (empty line)
+fn identity_headers(stream: &TlsStream<TcpStream>) -> Option<String> {
+    let (_, conn) = stream.get_ref();
+    let cert = conn.peer_certificates()?.first()?;
+    let (_, x509) = parse_x509_certificate(cert.as_ref()).ok()?;
+    Some(format!(
+        "X-Client-Cert-Subject: {}\r\nX-Client-Cert-Issuer: {}\r\nX-Client-Cert-Serial: {}\r\n\r\n",
+        x509.subject(),
+        x509.issuer(),
+        x509.raw_serial_as_string()
+    ))
+}
+
+/// Copy data from reader to writer then shutdown the writer (half close) once the reader is done
+async fn copy_and_shutdown<R, W>(reader: &mut R, writer: &mut W) -> std::io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let n = match tokio::io::copy(reader, writer).await {
+        Ok(n) => n,
+        // Note: some Tls clients close the connection without sending close_notify
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => 0,
+        Err(e) => return Err(e),
+    };
+    writer.shutdown().await?;
+    Ok(n)
+}
+
+async fn proxy_conn(
+    stream: TlsStream<TcpStream>,
+    peer_addr: SocketAddr,
+    config: &ProxyConfig,
+) -> AResult<()> {
+    let backend = tokio::time::timeout(
+        config.connect_timeout,
+        TcpStream::connect(&config.backend_addr),
+    )
+    .await
+    .map_err(|_| {
+        format!(
+            "no connection to backend after {:?}",
+            config.connect_timeout
+        )
+    })??;
+
+    let (mut backend_reader, mut backend_writer) = backend.into_split();
+    if config.identity_headers {
+        if let Some(headers) = identity_headers(&stream) {
+            backend_writer.write_all(headers.as_bytes()).await?;
+        }
+    }
+
+    let (mut client_reader, mut client_writer) = tokio::io::split(stream);
+    // Half close: client EOF -> shutdown (FIN) to the backend, backend EOF -> close_notify to the client
+    let (sent, received) = tokio::try_join!(
+        copy_and_shutdown(&mut client_reader, &mut backend_writer),
+        copy_and_shutdown(&mut backend_reader, &mut client_writer),
+    )?;
+    println!(
+        "[Proxy] {} <-> {} done (sent: {} bytes, received: {} bytes)",
+        peer_addr, config.backend_addr, sent, received
+    );
+    Ok(())
+}
+
+async fn handshake_and_proxy(
+    acceptor: TlsAcceptor,
+    socket: TcpStream,
+    peer_addr: SocketAddr,
+    config: Arc<ProxyConfig>,
+    stats: Arc<HandshakeStats>,
+) {
+    let Some(stream) = handshake(
+        &acceptor,
+        socket,
+        peer_addr,
+        config.handshake_timeout,
+        &stats,
+    )
+    .await
+    else {
+        return;
+    };
+    if let Err(e) = proxy_conn(stream, peer_addr, &config).await {
+        println!(
+            "[Proxy] {} <-> {} error: {}",
+            peer_addr, config.backend_addr, e
+        );
+    }
+}
+
+async fn serve() -> AResult<()> {
+    let (arg, options) = parse_args();
+
+    let (addr, cert, key, backend_addr) = match arg.len() {
+        4 => (&arg[0], &arg[1], &arg[2], arg[3].clone()),
+        _ => panic!("{}", USAGE),
+    };
+
+    let handshake_timeout = match options.get("handshake-timeout-ms") {
+        Some(ms) => Duration::from_millis(ms.parse::<u64>()?),
+        None => Duration::from_millis(DEFAULT_HANDSHAKE_TIMEOUT_MS),
+    };
+    let connect_timeout = match options.get("connect-timeout-ms") {
+        Some(ms) => Duration::from_millis(ms.parse::<u64>()?),
+        None => Duration::from_millis(DEFAULT_CONNECT_TIMEOUT_MS),
+    };
+    let config = Arc::new(ProxyConfig {
+        backend_addr,
+        handshake_timeout,
+        connect_timeout,
+        identity_headers: options.contains_key("identity-headers"),
+    });
+    println!("proxy config: {:?}", config);
+
+    let builder = TlsServerBuilder::from_pem_files(cert, key);
+    let builder = match options.get("client-ca") {
+        Some(client_ca) => {
+            println!("client auth (mTLS): enabled");
+            builder.client_auth_required(client_ca)
+        }
+        None => builder,
+    };
+    let acceptor = builder.build_acceptor()?;
+    let stats = Arc::new(HandshakeStats::default());
+
+    let listener = TcpListener::bind(&addr[..]).await?;
+    println!(
+        "[Tcp/Tls] Listening on {}, forwarding to {}",
+        addr, config.backend_addr
+    );
+    loop {
+        let (socket, peer_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("[Tcp] accept error: {}", e);
+                continue;
+            }
+        };
+        tokio::spawn(handshake_and_proxy(
+            acceptor.clone(),
+            socket,
+            peer_addr,
+            config.clone(),
+            stats.clone(),
+        ));
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    println!("Starting tcp/tls proxy");
+    if let Err(e) = serve().await {
+        println!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -1,17 +1,12 @@
-#![allow(clippy::bind_instead_of_map)]
-
-use std::env;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use tokio::net::{TcpListener, TcpStream};
 // Tls
-use rustls::server::WebPkiClientVerifier;
-use rustls::{RootCertStore, ServerConfig};
-use rustls_pemfile::{certs, pkcs8_private_keys};
-use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::crypto::aws_lc_rs::Ticketer;
+use rustls::server::{NoServerSessionStorage, ProducesTickets, StoresServerSessions};
+use tls_config_lib::{handshake, parse_args, parse_versions, HandshakeStats, TlsServerBuilder};
 use tokio_rustls::TlsAcceptor;
 
 // traits
@@ -21,23 +16,116 @@ use tokio::io::{ReadHalf, WriteHalf};
 // Easy error handling with async code
 type AResult<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
-pub fn load_certs<P>(path: P) -> std::io::Result<Vec<CertificateDer<'static>>>
-where
-    P: AsRef<Path>,
-{
-    let certfile = File::open(path).expect("cannot open certificate file");
-    let mut reader = BufReader::new(certfile);
-    certs(&mut reader).collect()
+const DEFAULT_HANDSHAKE_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_SESSION_CACHE_SIZE: usize = 256;
+const DEFAULT_SESSION_LIFETIME_SECS: u64 = 60 * 60;
+
+// Session value + creation time
+type SessionEntry = (Instant, Vec<u8>);
+
+/// Stateful session cache (server side): sessions are forgotten after `lifetime`, the oldest
+/// session is evicted when the cache is full
+#[derive(Debug)]
+struct ExpiringSessionCache {
+    sessions: Mutex<HashMap<Vec<u8>, SessionEntry>>,
+    max_size: usize,
+    lifetime: Duration,
+}
+
+impl ExpiringSessionCache {
+    fn new(max_size: usize, lifetime: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::with_capacity(max_size)),
+            max_size,
+            lifetime,
+        }
+    }
+}
+
+impl StoresServerSessions for ExpiringSessionCache {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, (created, _)| created.elapsed() < self.lifetime);
+        // Still full: evict the oldest session
+        if sessions.len() >= self.max_size && !sessions.contains_key(&key) {
+            let oldest = sessions
+                .iter()
+                .min_by_key(|(_, (created, _))| *created)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+        sessions.insert(key, (Instant::now(), value));
+        true
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .get(key)
+            .filter(|(created, _)| created.elapsed() < self.lifetime)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions
+            .remove(key)
+            .filter(|(created, _)| created.elapsed() < self.lifetime)
+            .map(|(_, value)| value)
+    }
+
+    fn can_cache(&self) -> bool {
+        true
+    }
+}
+
+/// Session tickets expiring after `lifetime` seconds: the ticket plaintext is prefixed with its
+/// issue time (the ticket keys are rotated by rustls::crypto::aws_lc_rs::Ticketer)
+#[derive(Debug)]
+struct ExpiringTicketer {
+    ticketer: Arc<dyn ProducesTickets>,
+    lifetime: u32,
+}
+
+impl ExpiringTicketer {
+    fn new(lifetime: u32) -> Result<Self, rustls::Error> {
+        Ok(Self {
+            ticketer: Ticketer::new()?,
+            lifetime,
+        })
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
 }
 
-pub fn load_keys<P>(path: P) -> std::io::Result<Vec<PrivateKeyDer<'static>>>
-where
-    P: AsRef<Path>,
-{
-    // TODO: support other keys
-    pkcs8_private_keys(&mut BufReader::new(File::open(path.as_ref())?))
-        .map(|k_| k_.and_then(|k| Ok(PrivateKeyDer::Pkcs8(k))))
-        .collect()
+impl ProducesTickets for ExpiringTicketer {
+    fn enabled(&self) -> bool {
+        self.ticketer.enabled()
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.lifetime
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        let mut stamped = Self::now().to_be_bytes().to_vec();
+        stamped.extend_from_slice(plain);
+        self.ticketer.encrypt(&stamped)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        let stamped = self.ticketer.decrypt(cipher)?;
+        let (issued, plain) = stamped.split_first_chunk::<8>()?;
+        let age = Self::now().saturating_sub(u64::from_be_bytes(*issued));
+        (age < u64::from(self.lifetime)).then(|| plain.to_vec())
+    }
 }
 
 async fn handle_conn(
@@ -96,50 +184,93 @@ async fn handle_conn(
 }
 
 async fn serve() -> AResult<()> {
-    // Skip args[0] (cmd line string) and only take first
-    let arg: Vec<String> = env::args().skip(1).take(3).collect();
+    let (arg, options) = parse_args();
 
     // let empty_str = String::new();
-    let panic_msg = "Use: cargo run -- 127.0.0.1:7070 cert.pem key.pem";
-    let (addr, cert, key, _enable_tls) = match arg.len() {
+    let panic_msg = "Use: cargo run -- 127.0.0.1:7070 cert.pem key.pem [handshake_timeout_ms] \
+        [--session-cache-size 256] [--session-lifetime 3600] [--ticket-lifetime 3600] \
+        [--tls-versions 1.2,1.3] [--alpn h2,http/1.1]";
+    let (addr, cert, key, handshake_timeout_ms) = match arg.len() {
         0..=2 => panic!("{}", panic_msg),
-        3 => (&arg[0], &arg[1], &arg[2], true),
+        3 => (&arg[0], &arg[1], &arg[2], DEFAULT_HANDSHAKE_TIMEOUT_MS),
+        4 => (&arg[0], &arg[1], &arg[2], arg[3].parse::<u64>()?),
         _ => panic!("{}", panic_msg),
     };
+    let handshake_timeout = Duration::from_millis(handshake_timeout_ms);
 
     println!("arg 1: {}", cert);
     println!("arg 2: {}", key);
+    println!("handshake timeout: {:?}", handshake_timeout);
 
-    let certs = load_certs(cert)?;
-    let mut keys = load_keys(key)?;
-
-    let mut root_store = RootCertStore::empty();
-    let mut pem = BufReader::new(File::open("certs/ca_signed_client_auth/root_ca.pem")?);
-    let ca_certs = rustls_pemfile::certs(&mut pem).map(|c| c.unwrap());
-    root_store.add_parsable_certificates(ca_certs);
-
-    let client_verifier = WebPkiClientVerifier::builder(root_store.into())
-        .build()
-        .unwrap();
+    // Session resumption
+    // Note: a session cache size of 0 disables the stateful cache
+    //       stateless session tickets are only enabled with --ticket-lifetime
+    let session_cache_size = match options.get("session-cache-size") {
+        Some(size) => size.parse::<usize>()?,
+        None => DEFAULT_SESSION_CACHE_SIZE,
+    };
+    let session_lifetime = match options.get("session-lifetime") {
+        Some(secs) => Duration::from_secs(secs.parse::<u64>()?),
+        None => Duration::from_secs(DEFAULT_SESSION_LIFETIME_SECS),
+    };
+    let ticket_lifetime = match options.get("ticket-lifetime") {
+        Some(secs) => Some(secs.parse::<u32>()?),
+        None => None,
+    };
+    println!(
+        "session cache: {} entries / lifetime: {:?}",
+        session_cache_size, session_lifetime
+    );
+    println!(
+        "session tickets: {}",
+        ticket_lifetime.map_or("disabled".to_string(), |l| format!("lifetime: {}s", l))
+    );
 
-    let is_auth_mandatory = client_verifier.client_auth_mandatory();
-    println!("Is client auth mandatory: {}", is_auth_mandatory);
+    // Client auth is mandatory: client certificates must be issued by our root CA
+    let mut builder = TlsServerBuilder::from_pem_files(cert, key)
+        .client_auth_required("certs/ca_signed_client_auth/root_ca.pem");
+    if let Some(versions) = options.get("tls-versions") {
+        builder = builder.versions(&parse_versions(versions)?);
+    }
+    if let Some(alpn) = options.get("alpn") {
+        builder = builder.alpn(&alpn.split(',').collect::<Vec<&str>>());
+    }
+    let mut config = builder.build()?;
 
-    let config = ServerConfig::builder()
-        .with_client_cert_verifier(client_verifier)
-        .with_single_cert(certs, keys.pop().ok_or("Unable to read key")?)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    config.session_storage = match session_cache_size {
+        0 => Arc::new(NoServerSessionStorage {}),
+        size => Arc::new(ExpiringSessionCache::new(size, session_lifetime)),
+    };
+    if let Some(lifetime) = ticket_lifetime {
+        config.ticketer = Arc::new(ExpiringTicketer::new(lifetime)?);
+    }
 
     let acceptor = TlsAcceptor::from(Arc::new(config));
 
+    let stats = Arc::new(HandshakeStats::default());
+
     let listener = TcpListener::bind(&addr[..]).await?;
     println!("[Tcp/Tls] Listening on {}", addr);
     loop {
-        let (socket, _addr) = listener.accept().await?;
-        let stream = acceptor.accept(socket).await?;
-        let (mut reader, mut writer) = tokio::io::split(stream);
+        // Note: accept errors (e.g. too many open files) are not fatal for the server
+        let (socket, peer_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                println!("[Tcp] accept error: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let stats = stats.clone();
 
-        tokio::spawn(async move { handle_conn(&mut reader, &mut writer, 1024).await });
+        tokio::spawn(async move {
+            if let Some(stream) =
+                handshake(&acceptor, socket, peer_addr, handshake_timeout, &stats).await
+            {
+                let (mut reader, mut writer) = tokio::io::split(stream);
+                handle_conn(&mut reader, &mut writer, 1024).await
+            }
+        });
     }
 }
 